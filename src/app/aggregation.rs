@@ -0,0 +1,246 @@
+use crossterm::event::KeyEvent;
+use mongodb::bson::{Bson, Document};
+
+use super::editor::TextEditor;
+use crate::{
+    config::SavedPipeline,
+    services::query::WRITE_STAGES,
+    syntax::{self, DisplayMode},
+};
+
+/// Part of the aggregation screen receiving key presses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregationFocus {
+    Editor,
+    Stages,
+    SaveName,
+    Saved,
+}
+
+#[derive(Debug, Clone)]
+pub struct PipelineStage {
+    pub operator: String,
+    pub stage: Document,
+    pub enabled: bool,
+}
+
+impl PipelineStage {
+    pub fn is_write(&self) -> bool {
+        WRITE_STAGES.contains(&self.operator.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct AggregationState {
+    pub editor: TextEditor,
+    pub stages: Vec<PipelineStage>,
+    pub parse_error: Option<String>,
+    pub selected_stage: usize,
+    pub focus: AggregationFocus,
+    pub preview: Vec<Document>,
+    // last stage included in the preview, None when it ran every stage
+    pub preview_stage: Option<usize>,
+    pub preview_scroll: usize,
+    pub save_name: String,
+    pub saved: Vec<SavedPipeline>,
+    pub selected_saved: usize,
+}
+
+impl AggregationState {
+    pub fn new() -> Self {
+        Self {
+            editor: TextEditor::new(),
+            stages: Vec::new(),
+            parse_error: None,
+            selected_stage: 0,
+            focus: AggregationFocus::Editor,
+            preview: Vec::new(),
+            preview_stage: None,
+            preview_scroll: 0,
+            save_name: String::new(),
+            saved: Vec::new(),
+            selected_saved: 0,
+        }
+    }
+
+    /// Starts a new pipeline matching `filter`, unless one is already being
+    /// edited.
+    pub fn open(&mut self, filter: Option<&Document>) {
+        self.focus = AggregationFocus::Editor;
+        if !self.editor.is_empty() {
+            return;
+        }
+
+        let filter = Bson::Document(filter.cloned().unwrap_or_default());
+        let match_stage = syntax::to_compact_string(&filter, DisplayMode::Relaxed);
+        self.set_input(&format!("[\n  {{\"$match\": {}}}\n]", match_stage));
+    }
+
+    pub fn set_input(&mut self, input: &str) {
+        self.editor.set_text(input);
+        self.parse_stages();
+    }
+
+    /// Passes `key` to the pipeline editor, returning false if it was not an
+    /// editing key.
+    pub fn handle_editor_key(&mut self, key: KeyEvent) -> bool {
+        let handled = self.editor.handle_key(key);
+        if handled {
+            self.parse_stages();
+        }
+        handled
+    }
+
+    /// Re-reads the stage list from the editor text, keeping each stage's
+    /// enabled flag by position.
+    pub fn parse_stages(&mut self) {
+        let stages = match parse_pipeline(&self.editor.text()) {
+            Ok(stages) => stages,
+            Err(e) => {
+                self.parse_error = Some(e);
+                return;
+            }
+        };
+
+        self.parse_error = None;
+        self.stages = stages
+            .into_iter()
+            .enumerate()
+            .map(|(i, stage)| PipelineStage {
+                operator: stage.keys().next().cloned().unwrap_or_default(),
+                enabled: self.stages.get(i).is_none_or(|old| old.enabled),
+                stage,
+            })
+            .collect();
+
+        if self.selected_stage >= self.stages.len() {
+            self.selected_stage = self.stages.len().saturating_sub(1);
+        }
+    }
+
+    pub fn toggle_focus(&mut self) {
+        self.focus = match self.focus {
+            AggregationFocus::Editor => AggregationFocus::Stages,
+            _ => AggregationFocus::Editor,
+        };
+    }
+
+    pub fn select_next_stage(&mut self) {
+        if !self.stages.is_empty() {
+            self.selected_stage = (self.selected_stage + 1) % self.stages.len();
+        }
+    }
+
+    pub fn select_prev_stage(&mut self) {
+        if !self.stages.is_empty() {
+            if self.selected_stage == 0 {
+                self.selected_stage = self.stages.len() - 1;
+            } else {
+                self.selected_stage -= 1;
+            }
+        }
+    }
+
+    pub fn toggle_selected_stage(&mut self) {
+        if let Some(stage) = self.stages.get_mut(self.selected_stage) {
+            stage.enabled = !stage.enabled;
+        }
+    }
+
+    /// Enabled stages up to and including `through` (every stage when
+    /// `None`), leaving out stages that would write data.
+    pub fn preview_pipeline(&self, through: Option<usize>) -> Vec<Document> {
+        let end = through.map_or(self.stages.len(), |i| i + 1);
+        self.stages
+            .iter()
+            .take(end)
+            .filter(|stage| stage.enabled && !stage.is_write())
+            .map(|stage| stage.stage.clone())
+            .collect()
+    }
+
+    pub fn has_write_stage(&self, through: Option<usize>) -> bool {
+        let end = through.map_or(self.stages.len(), |i| i + 1);
+        self.stages
+            .iter()
+            .take(end)
+            .any(|stage| stage.enabled && stage.is_write())
+    }
+
+    pub fn set_preview(&mut self, through: Option<usize>, documents: Vec<Document>) {
+        self.preview_stage = through;
+        self.preview = documents;
+        self.preview_scroll = 0;
+    }
+
+    pub fn scroll_preview_down(&mut self) {
+        self.preview_scroll += 1;
+    }
+
+    pub fn scroll_preview_up(&mut self) {
+        self.preview_scroll = self.preview_scroll.saturating_sub(1);
+    }
+
+    pub fn start_save(&mut self) {
+        self.focus = AggregationFocus::SaveName;
+        self.save_name.clear();
+    }
+
+    pub fn show_saved(&mut self, saved: Vec<SavedPipeline>) {
+        self.saved = saved;
+        self.selected_saved = 0;
+        self.focus = AggregationFocus::Saved;
+    }
+
+    pub fn select_next_saved(&mut self) {
+        if !self.saved.is_empty() {
+            self.selected_saved = (self.selected_saved + 1) % self.saved.len();
+        }
+    }
+
+    pub fn select_prev_saved(&mut self) {
+        if !self.saved.is_empty() {
+            if self.selected_saved == 0 {
+                self.selected_saved = self.saved.len() - 1;
+            } else {
+                self.selected_saved -= 1;
+            }
+        }
+    }
+
+    pub fn load_selected_saved(&mut self) {
+        if let Some(saved) = self.saved.get(self.selected_saved) {
+            let pipeline = saved.pipeline.clone();
+            self.stages.clear();
+            self.set_input(&pipeline);
+            self.focus = AggregationFocus::Editor;
+        }
+    }
+}
+
+impl Default for AggregationState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses an array of pipeline stages, each a single-operator document.
+/// Stages may use mongosh syntax such as `ObjectId("...")`.
+pub fn parse_pipeline(input: &str) -> Result<Vec<Document>, String> {
+    let value = syntax::parse_value(input).map_err(|e| format!("Invalid pipeline: {}", e))?;
+    let Bson::Array(stages) = value else {
+        return Err("A pipeline must be an array of stages".to_string());
+    };
+
+    stages
+        .into_iter()
+        .enumerate()
+        .map(|(i, stage)| match stage {
+            Bson::Document(stage) if stage.len() == 1 => Ok(stage),
+            _ => Err(format!(
+                "Stage {} must be a document with exactly one operator",
+                i + 1
+            )),
+        })
+        .collect()
+}
@@ -0,0 +1,65 @@
+use mongodb::bson::{Bson, Document};
+
+/// A field that differs between two versions of a document. Paths are
+/// dotted, e.g. `address.city`.
+#[derive(Debug, Clone)]
+pub enum FieldChange {
+    Added {
+        path: String,
+        value: Bson,
+    },
+    Removed {
+        path: String,
+        value: Bson,
+    },
+    Changed {
+        path: String,
+        before: Bson,
+        after: Bson,
+    },
+}
+
+/// Field-level changes from `before` to `after`. Nested documents are
+/// compared field by field; arrays and other values as a whole.
+pub fn diff_documents(before: &Document, after: &Document) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    diff_into(&mut changes, "", before, after);
+    changes
+}
+
+fn diff_into(changes: &mut Vec<FieldChange>, parent: &str, before: &Document, after: &Document) {
+    let path = |key: &str| {
+        if parent.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", parent, key)
+        }
+    };
+
+    for (key, old) in before {
+        match (old, after.get(key)) {
+            (_, None) => changes.push(FieldChange::Removed {
+                path: path(key),
+                value: old.clone(),
+            }),
+            (Bson::Document(old), Some(Bson::Document(new))) => {
+                diff_into(changes, &path(key), old, new);
+            }
+            (old, Some(new)) if new != old => changes.push(FieldChange::Changed {
+                path: path(key),
+                before: old.clone(),
+                after: new.clone(),
+            }),
+            _ => {}
+        }
+    }
+
+    for (key, new) in after {
+        if !before.contains_key(key) {
+            changes.push(FieldChange::Added {
+                path: path(key),
+                value: new.clone(),
+            });
+        }
+    }
+}
@@ -0,0 +1,477 @@
+use arboard::Clipboard;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+// snapshots kept for undo; the oldest are dropped first
+const UNDO_LIMIT: usize = 200;
+const INDENT: &str = "  ";
+
+#[derive(Debug, Clone)]
+struct Snapshot {
+    lines: Vec<String>,
+    row: usize,
+    col: usize,
+}
+
+// consecutive edits of the same kind are undone together
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EditKind {
+    Insert,
+    Delete,
+    Other,
+}
+
+/// Multi-line text buffer with a cursor, used for JSON filters and
+/// aggregation pipelines.
+///
+/// The cursor column counts characters, not bytes.
+#[derive(Debug, Clone)]
+pub struct TextEditor {
+    lines: Vec<String>,
+    row: usize,
+    col: usize,
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    last_edit: Option<EditKind>,
+}
+
+impl TextEditor {
+    pub fn new() -> Self {
+        Self {
+            lines: vec![String::new()],
+            row: 0,
+            col: 0,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            last_edit: None,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Cursor position as (line, character).
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(|line| line.trim().is_empty())
+    }
+
+    /// Replaces the whole buffer and puts the cursor at its end. Can be
+    /// undone like any other edit.
+    pub fn set_text(&mut self, text: &str) {
+        self.record(EditKind::Other);
+        self.lines = text.split('\n').map(str::to_string).collect();
+        self.move_to_end();
+    }
+
+    pub fn clear(&mut self) {
+        self.set_text("");
+    }
+
+    /// Applies an editing or movement key. Returns false for keys the editor
+    /// does not handle, so callers can give them another meaning.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+
+        match key.code {
+            KeyCode::Char('z') if ctrl => self.undo(),
+            KeyCode::Char('y') if ctrl => self.redo(),
+            KeyCode::Char('v') if ctrl => self.paste(),
+            KeyCode::Char('a') if ctrl => self.move_home(),
+            KeyCode::Char('e') if ctrl => self.move_end(),
+            KeyCode::Char('w') if ctrl => self.delete_word_back(),
+            KeyCode::Char('j') if ctrl => self.newline(),
+            KeyCode::Char('b') if alt => self.move_word_left(),
+            KeyCode::Char('f') if alt => self.move_word_right(),
+            KeyCode::Char(_) if ctrl || alt => return false,
+            KeyCode::Char(c) => self.insert_char(c),
+            KeyCode::Enter => self.newline(),
+            KeyCode::Backspace if ctrl || alt => self.delete_word_back(),
+            KeyCode::Backspace => self.backspace(),
+            KeyCode::Delete => self.delete(),
+            KeyCode::Left if ctrl || alt => self.move_word_left(),
+            KeyCode::Right if ctrl || alt => self.move_word_right(),
+            KeyCode::Left => self.move_left(),
+            KeyCode::Right => self.move_right(),
+            KeyCode::Up => self.move_up(),
+            KeyCode::Down => self.move_down(),
+            KeyCode::Home if ctrl => self.move_to_start(),
+            KeyCode::End if ctrl => self.move_to_end(),
+            KeyCode::Home => self.move_home(),
+            KeyCode::End => self.move_end(),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        if c == '\n' {
+            self.newline();
+            return;
+        }
+
+        // typing a word is undone in one step, whitespace starts a new one
+        let kind = if c.is_whitespace() {
+            EditKind::Other
+        } else {
+            EditKind::Insert
+        };
+        self.record(kind);
+
+        let at = self.byte_index(self.row, self.col);
+        self.lines[self.row].insert(at, c);
+        self.col += 1;
+    }
+
+    /// Inserts text as-is, without auto-indenting new lines.
+    pub fn insert_str(&mut self, text: &str) {
+        self.record(EditKind::Other);
+        for c in text.chars() {
+            match c {
+                '\r' => {}
+                '\n' => self.split_line(String::new()),
+                '\t' => {
+                    for c in INDENT.chars() {
+                        self.insert_raw(c);
+                    }
+                }
+                c => self.insert_raw(c),
+            }
+        }
+    }
+
+    /// Breaks the line at the cursor, indenting the new line like the
+    /// current one and one level deeper after an opening bracket.
+    pub fn newline(&mut self) {
+        self.record(EditKind::Other);
+
+        let line = &self.lines[self.row];
+        let indent: String = line.chars().take_while(|c| *c == ' ').collect();
+        let before = self.col.checked_sub(1).and_then(|i| line.chars().nth(i));
+        let after = line.chars().nth(self.col);
+
+        match before {
+            Some(open @ ('{' | '[')) => {
+                let inner = format!("{}{}", indent, INDENT);
+                self.split_line(inner);
+                // `{|}` becomes a block with the cursor on its own line
+                if after == Some(closing_bracket(open)) {
+                    let (row, col) = (self.row, self.col);
+                    self.split_line(indent);
+                    self.row = row;
+                    self.col = col;
+                }
+            }
+            _ => self.split_line(indent),
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if self.col == 0 && self.row == 0 {
+            return;
+        }
+        self.record(EditKind::Delete);
+
+        if self.col > 0 {
+            self.col -= 1;
+            let at = self.byte_index(self.row, self.col);
+            self.lines[self.row].remove(at);
+        } else {
+            let line = self.lines.remove(self.row);
+            self.row -= 1;
+            self.col = self.line_len(self.row);
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.col < self.line_len(self.row) {
+            self.record(EditKind::Delete);
+            let at = self.byte_index(self.row, self.col);
+            self.lines[self.row].remove(at);
+        } else if self.row + 1 < self.lines.len() {
+            self.record(EditKind::Delete);
+            let next = self.lines.remove(self.row + 1);
+            self.lines[self.row].push_str(&next);
+        }
+    }
+
+    pub fn delete_word_back(&mut self) {
+        let (row, col) = (self.row, self.col);
+        self.move_word_left();
+        if (self.row, self.col) == (row, col) {
+            return;
+        }
+
+        let (start_row, start_col) = (self.row, self.col);
+        self.record(EditKind::Other);
+
+        let tail = self.lines[row][self.byte_index(row, col)..].to_string();
+        let head_end = self.byte_index(start_row, start_col);
+        self.lines[start_row].truncate(head_end);
+        self.lines[start_row].push_str(&tail);
+        self.lines.drain(start_row + 1..=row);
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(snapshot) = self.undo.pop() {
+            let current = self.snapshot();
+            self.redo.push(current);
+            self.restore(snapshot);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(snapshot) = self.redo.pop() {
+            let current = self.snapshot();
+            self.undo.push(current);
+            self.restore(snapshot);
+        }
+    }
+
+    pub fn paste(&mut self) {
+        if let Ok(mut clipboard) = Clipboard::new()
+            && let Ok(text) = clipboard.get_text()
+        {
+            self.insert_str(&text);
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        self.last_edit = None;
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.col = self.line_len(self.row);
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        self.last_edit = None;
+        if self.col < self.line_len(self.row) {
+            self.col += 1;
+        } else if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = 0;
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        self.last_edit = None;
+        if self.row > 0 {
+            self.row -= 1;
+            self.col = self.col.min(self.line_len(self.row));
+        } else {
+            self.col = 0;
+        }
+    }
+
+    pub fn move_down(&mut self) {
+        self.last_edit = None;
+        if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = self.col.min(self.line_len(self.row));
+        } else {
+            self.col = self.line_len(self.row);
+        }
+    }
+
+    pub fn move_home(&mut self) {
+        self.last_edit = None;
+        self.col = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.last_edit = None;
+        self.col = self.line_len(self.row);
+    }
+
+    pub fn move_to_start(&mut self) {
+        self.last_edit = None;
+        self.row = 0;
+        self.col = 0;
+    }
+
+    pub fn move_to_end(&mut self) {
+        self.last_edit = None;
+        self.row = self.lines.len() - 1;
+        self.col = self.line_len(self.row);
+    }
+
+    /// Moves to the start of the previous word, crossing line breaks.
+    pub fn move_word_left(&mut self) {
+        self.last_edit = None;
+        if self.col == 0 {
+            self.move_left();
+            return;
+        }
+
+        let chars: Vec<char> = self.lines[self.row].chars().collect();
+        let mut col = self.col;
+        while col > 0 && !is_word_char(chars[col - 1]) {
+            col -= 1;
+        }
+        while col > 0 && is_word_char(chars[col - 1]) {
+            col -= 1;
+        }
+        self.col = col;
+    }
+
+    /// Moves past the end of the next word, crossing line breaks.
+    pub fn move_word_right(&mut self) {
+        self.last_edit = None;
+        let chars: Vec<char> = self.lines[self.row].chars().collect();
+        if self.col >= chars.len() {
+            self.move_right();
+            return;
+        }
+
+        let mut col = self.col;
+        while col < chars.len() && !is_word_char(chars[col]) {
+            col += 1;
+        }
+        while col < chars.len() && is_word_char(chars[col]) {
+            col += 1;
+        }
+        self.col = col;
+    }
+
+    /// Position of the bracket matching the one under the cursor, or the
+    /// one just before it.
+    pub fn matching_bracket(&self) -> Option<(usize, usize)> {
+        let line: Vec<char> = self.lines[self.row].chars().collect();
+        let (col, bracket) = [Some(self.col), self.col.checked_sub(1)]
+            .into_iter()
+            .flatten()
+            .find_map(|col| {
+                let c = *line.get(col)?;
+                "{}[]()".contains(c).then_some((col, c))
+            })?;
+
+        let forward = "{[(".contains(bracket);
+        let partner = if forward {
+            closing_bracket(bracket)
+        } else {
+            opening_bracket(bracket)
+        };
+
+        let mut depth = 0usize;
+        let mut row = self.row;
+        let mut chars = line;
+        let mut col = col as isize;
+        loop {
+            while col >= 0 && (col as usize) < chars.len() {
+                let c = chars[col as usize];
+                if c == bracket {
+                    depth += 1;
+                } else if c == partner {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some((row, col as usize));
+                    }
+                }
+                col += if forward { 1 } else { -1 };
+            }
+
+            if forward {
+                row += 1;
+                chars = self.lines.get(row)?.chars().collect();
+                col = 0;
+            } else {
+                row = row.checked_sub(1)?;
+                chars = self.lines[row].chars().collect();
+                col = chars.len() as isize - 1;
+            }
+        }
+    }
+
+    // pushes an undo snapshot unless this edit continues the previous one
+    fn record(&mut self, kind: EditKind) {
+        let continues = kind != EditKind::Other && self.last_edit == Some(kind);
+        if !continues {
+            let snapshot = self.snapshot();
+            self.undo.push(snapshot);
+            if self.undo.len() > UNDO_LIMIT {
+                self.undo.remove(0);
+            }
+        }
+        self.redo.clear();
+        self.last_edit = Some(kind);
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            lines: self.lines.clone(),
+            row: self.row,
+            col: self.col,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.lines = snapshot.lines;
+        self.row = snapshot.row;
+        self.col = snapshot.col;
+        self.last_edit = None;
+    }
+
+    fn insert_raw(&mut self, c: char) {
+        let at = self.byte_index(self.row, self.col);
+        self.lines[self.row].insert(at, c);
+        self.col += 1;
+    }
+
+    // moves the text after the cursor to a new line starting with `prefix`
+    fn split_line(&mut self, prefix: String) {
+        let at = self.byte_index(self.row, self.col);
+        let rest = self.lines[self.row].split_off(at);
+        self.col = prefix.chars().count();
+        self.row += 1;
+        self.lines.insert(self.row, prefix + &rest);
+    }
+
+    fn line_len(&self, row: usize) -> usize {
+        self.lines[row].chars().count()
+    }
+
+    fn byte_index(&self, row: usize, col: usize) -> usize {
+        let line = &self.lines[row];
+        line.char_indices()
+            .nth(col)
+            .map_or(line.len(), |(index, _)| index)
+    }
+}
+
+impl Default for TextEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+fn closing_bracket(c: char) -> char {
+    match c {
+        '{' => '}',
+        '[' => ']',
+        _ => ')',
+    }
+}
+
+fn opening_bracket(c: char) -> char {
+    match c {
+        '}' => '{',
+        ']' => '[',
+        _ => '(',
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::PathBuf,
+    process::Command,
+};
+
+// editor used when neither $VISUAL nor $EDITOR is set
+const DEFAULT_EDITOR: &str = "vi";
+
+use mongodb::bson::Document;
+use rand::RngCore;
+
+use crate::{
+    config::ConnectionProfile,
+    syntax::{self, DisplayMode},
+};
+
+/// What a document edited in the external editor is for.
+#[derive(Debug, Clone)]
+pub enum EditPurpose {
+    Insert {
+        database: String,
+        collection: String,
+    },
+    /// Replace `original`, as it was read from the server, with the edit.
+    Replace {
+        database: String,
+        collection: String,
+        original: Document,
+    },
+    /// Save a connection profile, replacing the one named `original`.
+    Profile { original: Option<String> },
+}
+
+/// Text to open in the external editor. The main loop suspends the TUI to
+/// run the editor and hands the saved text back to the handler.
+#[derive(Debug, Clone)]
+pub struct ExternalEdit {
+    pub purpose: EditPurpose,
+    pub text: String,
+}
+
+impl ExternalEdit {
+    /// Starting text for a new document in `database.collection`.
+    pub fn insert(database: &str, collection: &str) -> Self {
+        let text = format!(
+            "// New document for {}.{}\n\
+             // mongosh syntax and Extended JSON are accepted, e.g. ISODate(\"2024-01-01\").\n\
+             // Save and quit to insert it, or delete everything to cancel.\n\
+             {{\n  \n}}\n",
+            database, collection
+        );
+        Self {
+            purpose: EditPurpose::Insert {
+                database: database.to_string(),
+                collection: collection.to_string(),
+            },
+            text,
+        }
+    }
+
+    /// A copy of `document`, without its `_id`, to insert into
+    /// `database.collection`.
+    pub fn duplicate(database: &str, collection: &str, mut document: Document) -> Self {
+        document.remove("_id");
+        let text = format!(
+            "// Copy of a document for {}.{}\n\
+             // _id was removed so a new one is generated; add one to choose it yourself.\n\
+             // Save and quit to insert it, or delete everything to cancel.\n\
+             {}\n",
+            database,
+            collection,
+            syntax::to_pretty_string(&document, DisplayMode::Canonical)
+        );
+        Self {
+            purpose: EditPurpose::Insert {
+                database: database.to_string(),
+                collection: collection.to_string(),
+            },
+            text,
+        }
+    }
+
+    /// `original` as canonical Extended JSON, so every value keeps its
+    /// BSON type when saved back.
+    pub fn replace(database: &str, collection: &str, original: Document) -> Self {
+        let text = format!(
+            "// Editing a document in {}.{}\n\
+             // Values are canonical Extended JSON so their types survive; keep _id as it is.\n\
+             // Save and quit to review the changes before they are written.\n\
+             {}\n",
+            database,
+            collection,
+            syntax::to_pretty_string(&original, DisplayMode::Canonical)
+        );
+        Self {
+            purpose: EditPurpose::Replace {
+                database: database.to_string(),
+                collection: collection.to_string(),
+                original,
+            },
+            text,
+        }
+    }
+
+    /// `profile` as TOML, with the optional keys listed in a comment.
+    /// `original` names the saved profile it replaces, if any.
+    pub fn profile(profile: &ConnectionProfile, original: Option<String>) -> Self {
+        let text = format!(
+            "# Connection profile, saved to ~/.mongonaut/profiles.toml\n\
+             # Optional keys: default_database, color (\"green\", \"#ff8800\"),\n\
+             # read_preference (primary, primaryPreferred, secondary, secondaryPreferred,\n\
+             # nearest), connect_timeout_seconds, server_selection_timeout_seconds,\n\
+             # tls, tls_ca_file, tls_cert_key_file (PEM with certificate and key),\n\
+             # tls_allow_invalid_certificates, tls_allow_invalid_hostnames, read_only,\n\
+             # and an [ssh_tunnel] table with host, port, user and key_file (else ssh-agent).\n\
+             # Save and quit to store it, or delete everything to cancel.\n\
+             {}",
+            toml::to_string(profile).unwrap_or_default()
+        );
+        Self {
+            purpose: EditPurpose::Profile { original },
+            text,
+        }
+    }
+
+    // file extension that lets the editor pick the right highlighting
+    pub fn extension(&self) -> &'static str {
+        match self.purpose {
+            EditPurpose::Profile { .. } => "toml",
+            _ => "js",
+        }
+    }
+
+    pub fn with_text(&self, text: String) -> Self {
+        Self {
+            purpose: self.purpose.clone(),
+            text,
+        }
+    }
+}
+
+/// Opens `text` in `$VISUAL` or `$EDITOR`, as a file ending in
+/// `extension`, and returns the saved contents. Blocks until the editor
+/// exits; the terminal must not be in raw mode.
+pub fn edit_text(text: &str, extension: &str) -> io::Result<String> {
+    let path = create_temp_file(text, extension)?;
+
+    let result = run_editor(&path).and_then(|_| fs::read_to_string(&path));
+    let _ = fs::remove_file(&path);
+    result
+}
+
+fn run_editor(path: &PathBuf) -> io::Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string());
+
+    // allow editors with arguments, e.g. `code --wait`
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or(DEFAULT_EDITOR);
+    let status = Command::new(program).args(parts).arg(path).status()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{} exited with {}",
+            program, status
+        )))
+    }
+}
+
+// documents may hold secrets, so the file gets a name nobody can guess
+// or claim first and is readable only by the user
+fn create_temp_file(text: &str, extension: &str) -> io::Result<PathBuf> {
+    loop {
+        let mut random = [0u8; 8];
+        rand::rng().fill_bytes(&mut random);
+        let name: String = random.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let path = env::temp_dir().join(format!("mongonaut-{}.{}", name, extension));
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        match options.open(&path) {
+            Ok(mut file) => {
+                if let Err(e) = file.write_all(text.as_bytes()) {
+                    let _ = fs::remove_file(&path);
+                    return Err(e);
+                }
+                return Ok(path);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// True if `text` holds nothing but whitespace and comments starting with
+/// `comment`.
+pub fn is_blank(text: &str, comment: &str) -> bool {
+    text.lines()
+        .map(str::trim)
+        .all(|line| line.is_empty() || line.starts_with(comment))
+}
@@ -0,0 +1,178 @@
+use mongodb::bson::{Bson, Document};
+
+use crate::syntax::{self, DisplayMode};
+
+const MIN_WIDTH: u16 = 3;
+const MAX_AUTO_WIDTH: u16 = 30;
+const MAX_WIDTH: u16 = 120;
+
+/// A top-level field shown as a column of the table view.
+#[derive(Debug, Clone)]
+pub struct GridColumn {
+    pub name: String,
+    pub width: u16,
+    pub hidden: bool,
+}
+
+/// Columns of the table view. Columns follow the fields of the loaded
+/// documents, while width, visibility and order chosen by the user are kept
+/// for fields that stay.
+#[derive(Debug, Default)]
+pub struct GridState {
+    pub columns: Vec<GridColumn>,
+    pub selected_column: usize,
+    // leftmost column shown; the view scrolls further if the selected
+    // column does not fit
+    pub first_column: usize,
+}
+
+impl GridState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Re-infers columns from `documents`, `_id` first and then in order of
+    /// first appearance.
+    pub fn update_columns(&mut self, documents: &[Document]) {
+        let mut names: Vec<&str> = Vec::new();
+        for doc in documents {
+            for key in doc.keys() {
+                if !names.contains(&key.as_str()) {
+                    names.push(key);
+                }
+            }
+        }
+        if let Some(index) = names.iter().position(|name| *name == "_id") {
+            let id = names.remove(index);
+            names.insert(0, id);
+        }
+
+        // known columns keep their place, new ones are appended
+        let mut columns: Vec<GridColumn> = self
+            .columns
+            .iter()
+            .filter(|column| names.contains(&column.name.as_str()))
+            .cloned()
+            .collect();
+        for name in names {
+            if !columns.iter().any(|column| column.name == name) {
+                columns.push(GridColumn {
+                    name: name.to_string(),
+                    width: auto_width(name, documents),
+                    hidden: false,
+                });
+            }
+        }
+
+        self.columns = columns;
+        self.clamp_selection();
+    }
+
+    pub fn visible_columns(&self) -> impl Iterator<Item = (usize, &GridColumn)> {
+        self.columns
+            .iter()
+            .enumerate()
+            .filter(|(_, column)| !column.hidden)
+    }
+
+    pub fn select_next_column(&mut self) {
+        if let Some(index) = self.next_visible(self.selected_column + 1..self.columns.len()) {
+            self.selected_column = index;
+        }
+    }
+
+    pub fn select_prev_column(&mut self) {
+        if let Some(index) = self.prev_visible(0..self.selected_column) {
+            self.selected_column = index;
+            self.first_column = self.first_column.min(index);
+        }
+    }
+
+    pub fn resize_selected(&mut self, delta: i16) {
+        if let Some(column) = self.columns.get_mut(self.selected_column) {
+            column.width = column
+                .width
+                .saturating_add_signed(delta)
+                .clamp(MIN_WIDTH, MAX_WIDTH);
+        }
+    }
+
+    /// Swaps the selected column with its visible neighbour on the left
+    /// (`-1`) or right (`1`).
+    pub fn move_selected(&mut self, direction: isize) {
+        let neighbour = if direction < 0 {
+            self.prev_visible(0..self.selected_column)
+        } else {
+            self.next_visible(self.selected_column + 1..self.columns.len())
+        };
+        if let Some(index) = neighbour {
+            self.columns.swap(self.selected_column, index);
+            self.selected_column = index;
+            self.first_column = self.first_column.min(index);
+        }
+    }
+
+    pub fn hide_selected(&mut self) {
+        if self.visible_columns().count() <= 1 {
+            return;
+        }
+        if let Some(column) = self.columns.get_mut(self.selected_column) {
+            column.hidden = true;
+        }
+        self.clamp_selection();
+    }
+
+    pub fn show_all(&mut self) {
+        for column in &mut self.columns {
+            column.hidden = false;
+        }
+    }
+
+    pub fn hidden_count(&self) -> usize {
+        self.columns.iter().filter(|column| column.hidden).count()
+    }
+
+    // moves the selection onto a visible column
+    fn clamp_selection(&mut self) {
+        let selected = self
+            .selected_column
+            .min(self.columns.len().saturating_sub(1));
+        self.selected_column = self
+            .next_visible(selected..self.columns.len())
+            .or_else(|| self.prev_visible(0..selected))
+            .unwrap_or(0);
+        self.first_column = self.first_column.min(self.selected_column);
+    }
+
+    fn next_visible(&self, mut range: std::ops::Range<usize>) -> Option<usize> {
+        range.find(|i| !self.columns[*i].hidden)
+    }
+
+    fn prev_visible(&self, range: std::ops::Range<usize>) -> Option<usize> {
+        range.rev().find(|i| !self.columns[*i].hidden)
+    }
+}
+
+/// Text of a table cell: nested values are summarized, e.g. `{3 fields}`.
+pub fn cell_text(value: Option<&Bson>, mode: DisplayMode) -> String {
+    match value {
+        None => String::new(),
+        Some(value) => {
+            syntax::summary(value).unwrap_or_else(|| syntax::to_compact_string(value, mode))
+        }
+    }
+}
+
+fn auto_width(name: &str, documents: &[Document]) -> u16 {
+    let widest = documents
+        .iter()
+        .map(|doc| {
+            cell_text(doc.get(name), DisplayMode::Relaxed)
+                .chars()
+                .count()
+        })
+        .max()
+        .unwrap_or(0)
+        .max(name.chars().count());
+    (widest as u16).clamp(MIN_WIDTH, MAX_AUTO_WIDTH)
+}
@@ -0,0 +1,2233 @@
+use arboard::Clipboard;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use mongodb::{
+    bson::{Bson, Document, doc},
+    options::UpdateModifications,
+};
+
+use super::{
+    aggregation::AggregationFocus,
+    connection_form::ConnectionForm,
+    diff,
+    external::{self, EditPurpose, ExternalEdit},
+    journal::{self, JournalChange, JournalEntry},
+    modal::Modal,
+    screen::Screen,
+    state::{AppState, ConnectionFocus, ContentView, PagingMode, QueryTarget, StreamFetch},
+    task::{FetchPurpose, TaskManager, TaskResult},
+    update::{self, UpdateSample},
+};
+use crate::{
+    config::{ConnectionHistory, ConnectionProfile, ConnectionProfiles, SavedPipelines},
+    credentials::{self, CredentialStore},
+    error::AppError,
+    models::{Keyset, QueryParams},
+    services::{connection::ConnectionService, query::QueryService},
+    syntax::{self, DisplayMode},
+};
+
+/// Long-lived services the key handlers need besides the UI state.
+pub struct Context {
+    pub conn_service: ConnectionService,
+    pub history: ConnectionHistory,
+    pub tasks: TaskManager,
+    // unlocked on the first connection that needs a password
+    pub credentials: Option<CredentialStore>,
+}
+
+impl Context {
+    pub fn new(history: ConnectionHistory) -> Self {
+        Self {
+            conn_service: ConnectionService::new(),
+            history,
+            tasks: TaskManager::new(),
+            credentials: None,
+        }
+    }
+}
+
+pub fn handle_key(state: &mut AppState, ctx: &mut Context, key: KeyEvent) {
+    if state.modal.is_some() {
+        handle_modal_key(state, ctx, key);
+        return;
+    }
+
+    if ctx.tasks.is_busy() && is_cancel_key(state, key) {
+        cancel_task(state, ctx);
+        return;
+    }
+
+    match state.current_screen {
+        Screen::Connection => handle_connection_key(state, ctx, key),
+        Screen::DatabaseList => handle_database_list_key(state, ctx, key),
+        Screen::CollectionList => handle_collection_list_key(state, ctx, key),
+        Screen::DocumentView => handle_document_view_key(state, ctx, key),
+        Screen::Aggregation => handle_aggregation_key(state, ctx, key),
+        Screen::Update => handle_update_key(state, ctx, key),
+    }
+}
+
+// Esc cancels anywhere; Ctrl+C only outside the connection screen, where it
+// still quits
+fn is_cancel_key(state: &AppState, key: KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Esc => true,
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state.current_screen != Screen::Connection
+        }
+        _ => false,
+    }
+}
+
+/// Aborts the running read. Results are only committed to the state when a
+/// task finishes, so the previous documents and filter stay in place.
+/// Writes are not cancelled: the server may already have applied them.
+fn cancel_task(state: &mut AppState, ctx: &mut Context) {
+    if ctx.tasks.abort() {
+        // a write still running keeps the spinner
+        state.set_loading(ctx.tasks.is_writing());
+        state.set_notice(Some("Operation cancelled".to_string()));
+    } else if ctx.tasks.is_writing() {
+        state.set_notice(Some(
+            "A write is running and cannot be cancelled".to_string(),
+        ));
+    }
+}
+
+// writes run to completion, one at a time
+fn spawn_write<F>(state: &mut AppState, ctx: &mut Context, message: &str, future: F)
+where
+    F: Future<Output = TaskResult> + Send + 'static,
+{
+    if ctx.tasks.spawn_write(future) {
+        state.start_loading(message);
+        state.write_message = Some(message.to_string());
+    } else {
+        state.set_error(Some("Wait for the running write to finish".to_string()));
+    }
+}
+
+fn handle_modal_key(state: &mut AppState, ctx: &mut Context, key: KeyEvent) {
+    let Some(modal) = state.modal.take() else {
+        return;
+    };
+
+    match modal {
+        Modal::Passphrase {
+            mut input,
+            first,
+            creating,
+            error,
+            uri,
+            profile,
+        } => match key.code {
+            // go on without the credential file
+            KeyCode::Esc => connect(state, ctx, uri, profile, false),
+            KeyCode::Enter if input.is_empty() => {
+                state.modal = Some(Modal::Passphrase {
+                    input,
+                    first,
+                    creating,
+                    error: Some("The passphrase cannot be empty".to_string()),
+                    uri,
+                    profile,
+                });
+            }
+            KeyCode::Enter if creating && first.is_none() => {
+                state.modal = Some(Modal::Passphrase {
+                    input: String::new(),
+                    first: Some(input),
+                    creating,
+                    error: None,
+                    uri,
+                    profile,
+                });
+            }
+            KeyCode::Enter if creating && first.as_ref() != Some(&input) => {
+                state.modal = Some(Modal::Passphrase {
+                    input: String::new(),
+                    first: None,
+                    creating,
+                    error: Some("The passphrases did not match; try again".to_string()),
+                    uri,
+                    profile,
+                });
+            }
+            KeyCode::Enter => unlock_credentials(state, ctx, input, uri, profile),
+            code => {
+                match code {
+                    KeyCode::Char(c) => input.push(c),
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    _ => {}
+                }
+                state.modal = Some(Modal::Passphrase {
+                    input,
+                    first,
+                    creating,
+                    error,
+                    uri,
+                    profile,
+                });
+            }
+        },
+        Modal::Password {
+            key: name,
+            mut input,
+            uri,
+            profile,
+        } => match key.code {
+            KeyCode::Esc => {
+                state.set_notice(Some("Connection cancelled".to_string()));
+            }
+            KeyCode::Enter => {
+                let password = credentials::percent_encode(&input);
+                let remember = ctx.credentials.is_some().then(|| (name, password.clone()));
+                let uri = credentials::with_password(&uri, &password);
+                start_connect(state, ctx, uri, profile, remember);
+            }
+            code => {
+                match code {
+                    KeyCode::Char(c) => input.push(c),
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    _ => {}
+                }
+                state.modal = Some(Modal::Password {
+                    key: name,
+                    input,
+                    uri,
+                    profile,
+                });
+            }
+        },
+        Modal::ConfirmDeleteProfile { name } => match key.code {
+            KeyCode::Enter | KeyCode::Char('y') => delete_profile(state, &name),
+            KeyCode::Esc | KeyCode::Char('n') => {
+                state.set_notice(Some("Delete cancelled".to_string()));
+            }
+            _ => state.modal = Some(Modal::ConfirmDeleteProfile { name }),
+        },
+        Modal::EditError { edit, message } => match key.code {
+            KeyCode::Enter | KeyCode::Char('e') => {
+                state.pending_edit = Some(edit);
+            }
+            KeyCode::Esc => {
+                state.set_notice(Some("Discarded the edited document".to_string()));
+            }
+            _ => state.modal = Some(Modal::EditError { edit, message }),
+        },
+        Modal::ConfirmDelete {
+            database,
+            collection,
+            filter,
+            count,
+            bulk,
+            mut typed,
+        } => match key.code {
+            KeyCode::Esc => {
+                state.set_notice(Some("Delete cancelled".to_string()));
+            }
+            KeyCode::Enter if !bulk || typed == collection => {
+                delete_documents(state, ctx, database, collection, filter);
+            }
+            KeyCode::Char('y') if !bulk => {
+                delete_documents(state, ctx, database, collection, filter);
+            }
+            KeyCode::Char('n') if !bulk => {
+                state.set_notice(Some("Delete cancelled".to_string()));
+            }
+            code => {
+                if bulk {
+                    match code {
+                        KeyCode::Char(c) => typed.push(c),
+                        KeyCode::Backspace => {
+                            typed.pop();
+                        }
+                        _ => {}
+                    }
+                }
+                state.modal = Some(Modal::ConfirmDelete {
+                    database,
+                    collection,
+                    filter,
+                    count,
+                    bulk,
+                    typed,
+                });
+            }
+        },
+        Modal::ConfirmUpdate {
+            database,
+            collection,
+            filter,
+            update,
+            count,
+        } => match key.code {
+            KeyCode::Enter | KeyCode::Char('y') => {
+                update_documents(state, ctx, database, collection, filter, update);
+            }
+            KeyCode::Esc | KeyCode::Char('n') => {
+                state.set_notice(Some("Update cancelled".to_string()));
+            }
+            _ => {
+                state.modal = Some(Modal::ConfirmUpdate {
+                    database,
+                    collection,
+                    filter,
+                    update,
+                    count,
+                })
+            }
+        },
+        Modal::CloneTarget {
+            document,
+            databases,
+            mut database,
+            collections,
+            mut collection,
+            mut choosing_collection,
+            mut loading,
+        } => {
+            match key.code {
+                KeyCode::Enter if !loading => {
+                    state.pending_edit = Some(ExternalEdit::duplicate(
+                        &databases[database],
+                        &collections[collection],
+                        document,
+                    ));
+                    return;
+                }
+                KeyCode::Esc => {
+                    if loading {
+                        cancel_task(state, ctx);
+                    }
+                    return;
+                }
+                KeyCode::Up | KeyCode::Down | KeyCode::Tab | KeyCode::Char('j' | 'k') => {
+                    choosing_collection = !choosing_collection;
+                }
+                KeyCode::Left | KeyCode::Right | KeyCode::Char('h' | 'l')
+                    if !(choosing_collection && loading) =>
+                {
+                    let forward = matches!(key.code, KeyCode::Right | KeyCode::Char('l'));
+                    let (index, len) = if choosing_collection {
+                        (&mut collection, collections.len())
+                    } else {
+                        (&mut database, databases.len())
+                    };
+                    *index = if forward {
+                        (*index + 1) % len
+                    } else {
+                        (*index + len - 1) % len
+                    };
+                    if !choosing_collection {
+                        loading = true;
+                        load_clone_collections(state, ctx, databases[database].clone());
+                    }
+                }
+                _ => {}
+            }
+            state.modal = Some(Modal::CloneTarget {
+                document,
+                databases,
+                database,
+                collections,
+                collection,
+                choosing_collection,
+                loading,
+            });
+        }
+        Modal::ConfirmUndo => match key.code {
+            KeyCode::Enter | KeyCode::Char('y') => undo_last_write(state, ctx),
+            KeyCode::Esc | KeyCode::Char('n') => {}
+            _ => state.modal = Some(Modal::ConfirmUndo),
+        },
+        Modal::ConfirmReplace {
+            edit,
+            replacement,
+            changes,
+        } => match key.code {
+            KeyCode::Enter | KeyCode::Char('y') => {
+                if let EditPurpose::Replace {
+                    database,
+                    collection,
+                    original,
+                } = edit.purpose
+                {
+                    replace_document(state, ctx, database, collection, original, replacement);
+                }
+            }
+            KeyCode::Char('e') => {
+                state.pending_edit = Some(edit);
+            }
+            KeyCode::Esc | KeyCode::Char('n') => {
+                state.set_notice(Some("Discarded the edited document".to_string()));
+            }
+            _ => {
+                state.modal = Some(Modal::ConfirmReplace {
+                    edit,
+                    replacement,
+                    changes,
+                })
+            }
+        },
+    }
+}
+
+fn handle_connection_key(state: &mut AppState, ctx: &mut Context, key: KeyEvent) {
+    match key.code {
+        KeyCode::Char('c' | 'q') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state.quit();
+        }
+        KeyCode::Tab => {
+            state.next_connection_focus();
+        }
+        _ => match state.connection_focus {
+            ConnectionFocus::Input if state.connection_form.is_some() => {
+                handle_connection_form_key(state, ctx, key)
+            }
+            ConnectionFocus::Input => handle_uri_input_key(state, ctx, key),
+            ConnectionFocus::Profiles => handle_profiles_key(state, ctx, key),
+            ConnectionFocus::History => handle_history_key(state, key),
+        },
+    }
+}
+
+fn handle_uri_input_key(state: &mut AppState, ctx: &mut Context, key: KeyEvent) {
+    match key.code {
+        KeyCode::Char('v') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            if let Ok(mut clipboard) = Clipboard::new()
+                && let Ok(text) = clipboard.get_text()
+            {
+                for c in text.chars() {
+                    state.push_char(c);
+                }
+            }
+        }
+        KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state.pending_edit = Some(new_profile_edit(state));
+        }
+        KeyCode::Char('f') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            match ConnectionForm::from_uri(&state.connection_input) {
+                Ok(form) => state.connection_form = Some(form),
+                Err(e) => state.set_error(Some(e)),
+            }
+        }
+        KeyCode::Char(c) => {
+            state.push_char(c);
+        }
+        KeyCode::Backspace => {
+            state.pop_char();
+        }
+        KeyCode::Esc => {
+            state.clear_input();
+            state.set_error(None);
+        }
+        KeyCode::Enter => {
+            let uri = state.connection_input.clone();
+            connect(state, ctx, uri, None, true);
+        }
+        _ => {}
+    }
+}
+
+// every change to a field is written back to the URI
+fn handle_connection_form_key(state: &mut AppState, ctx: &mut Context, key: KeyEvent) {
+    let Some(form) = state.connection_form.as_mut() else {
+        return;
+    };
+    match key.code {
+        KeyCode::Char('f') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state.connection_form = None;
+            return;
+        }
+        KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state.pending_edit = Some(new_profile_edit(state));
+            return;
+        }
+        KeyCode::Esc => {
+            state.connection_form = None;
+            state.set_error(None);
+            return;
+        }
+        KeyCode::Enter => {
+            let uri = state.connection_input.clone();
+            state.start_loading("Checking connection settings...");
+            ctx.tasks.spawn(async move {
+                let result = ConnectionService::validate(&uri).await;
+                TaskResult::UriValidated { uri, result }
+            });
+            return;
+        }
+        KeyCode::Up => form.select_prev(),
+        KeyCode::Down => form.select_next(),
+        KeyCode::Left => form.cycle(false),
+        KeyCode::Right => form.cycle(true),
+        KeyCode::Char(' ') if form.selected_field().is_choice() => form.cycle(true),
+        KeyCode::Char(c) => form.push_char(c),
+        KeyCode::Backspace => form.pop_char(),
+        _ => return,
+    }
+    state.connection_input = form.to_uri();
+}
+
+fn handle_profiles_key(state: &mut AppState, ctx: &mut Context, key: KeyEvent) {
+    match key.code {
+        KeyCode::Up | KeyCode::Char('k') => {
+            state.select_prev_profile();
+        }
+        KeyCode::Down | KeyCode::Char('j') => {
+            state.select_next_profile();
+        }
+        KeyCode::Enter => {
+            if let Some(profile) = state.get_selected_profile().cloned() {
+                connect(state, ctx, profile.uri.clone(), Some(profile), true);
+            }
+        }
+        KeyCode::Char('n') => {
+            state.pending_edit = Some(new_profile_edit(state));
+        }
+        KeyCode::Char('e') => {
+            if let Some(profile) = state.get_selected_profile() {
+                let edit = ExternalEdit::profile(profile, Some(profile.name.clone()));
+                state.pending_edit = Some(edit);
+            }
+        }
+        KeyCode::Char('d') => {
+            if let Some(profile) = state.get_selected_profile() {
+                state.modal = Some(Modal::ConfirmDeleteProfile {
+                    name: profile.name.clone(),
+                });
+            }
+        }
+        KeyCode::Esc => {
+            state.connection_focus = ConnectionFocus::Input;
+        }
+        _ => {}
+    }
+}
+
+fn handle_history_key(state: &mut AppState, key: KeyEvent) {
+    match key.code {
+        KeyCode::Up | KeyCode::Char('k') => {
+            state.select_prev_history();
+        }
+        KeyCode::Down | KeyCode::Char('j') => {
+            state.select_next_history();
+        }
+        KeyCode::Enter => {
+            if let Some(uri) = state.get_selected_history_uri() {
+                state.connection_input = uri.clone();
+                state.connection_focus = ConnectionFocus::Input;
+            }
+        }
+        KeyCode::Esc => {
+            state.connection_focus = ConnectionFocus::Input;
+        }
+        _ => {}
+    }
+}
+
+// connects to `uri`, first unlocking the credential file (unless
+// `use_store` is false) when the URI needs a password, and asking for the
+// password if neither the URI nor the file has it
+fn connect(
+    state: &mut AppState,
+    ctx: &mut Context,
+    uri: String,
+    profile: Option<ConnectionProfile>,
+    use_store: bool,
+) {
+    let Some(key) = credentials::credential_key(&uri) else {
+        start_connect(state, ctx, uri, profile, None);
+        return;
+    };
+    if use_store && ctx.credentials.is_none() {
+        state.modal = Some(Modal::Passphrase {
+            input: String::new(),
+            first: None,
+            creating: !CredentialStore::exists(),
+            error: None,
+            uri,
+            profile,
+        });
+        return;
+    }
+
+    let stored = ctx
+        .credentials
+        .as_ref()
+        .and_then(|store| store.get(&key))
+        .map(str::to_string);
+    match (credentials::password(&uri), stored) {
+        (Some(password), stored) => {
+            let remember = (ctx.credentials.is_some() && stored.as_ref() != Some(&password))
+                .then_some((key, password));
+            start_connect(state, ctx, uri, profile, remember);
+        }
+        (None, Some(password)) => {
+            let uri = credentials::with_password(&uri, &password);
+            start_connect(state, ctx, uri, profile, None);
+        }
+        (None, None) => {
+            state.modal = Some(Modal::Password {
+                key,
+                input: String::new(),
+                uri,
+                profile,
+            });
+        }
+    }
+}
+
+fn start_connect(
+    state: &mut AppState,
+    ctx: &mut Context,
+    uri: String,
+    profile: Option<ConnectionProfile>,
+    remember: Option<(String, String)>,
+) {
+    match &profile {
+        Some(profile) => state.start_loading(&format!("Connecting to {}...", profile.name)),
+        None => state.start_loading("Connecting..."),
+    }
+    ctx.tasks.spawn(async move {
+        let result = ConnectionService::connect(&uri, profile.as_ref()).await;
+        TaskResult::Connected {
+            uri,
+            profile,
+            remember,
+            result,
+        }
+    });
+}
+
+// key derivation takes a moment, so it runs as a background task
+fn unlock_credentials(
+    state: &mut AppState,
+    ctx: &mut Context,
+    passphrase: String,
+    uri: String,
+    profile: Option<ConnectionProfile>,
+) {
+    state.start_loading("Unlocking credentials...");
+    ctx.tasks.spawn(async move {
+        let result = tokio::task::spawn_blocking(move || CredentialStore::unlock(&passphrase))
+            .await
+            .unwrap_or_else(|e| Err(AppError::Credentials(e.to_string())));
+        TaskResult::CredentialsUnlocked {
+            uri,
+            profile,
+            result,
+        }
+    });
+}
+
+// a new profile for the URI being typed, without its password
+fn new_profile_edit(state: &AppState) -> ExternalEdit {
+    let profile = ConnectionProfile::new(credentials::strip_password(&state.connection_input));
+    ExternalEdit::profile(&profile, None)
+}
+
+// stores a profile written in the external editor; anything that keeps it
+// from being saved reopens it with the reason
+fn save_profile(state: &mut AppState, edit: ExternalEdit, original: Option<String>, text: String) {
+    if external::is_blank(&text, "#") {
+        state.set_notice(Some("Nothing to save".to_string()));
+        return;
+    }
+
+    let result = toml::from_str::<ConnectionProfile>(&text)
+        .map_err(|e| match e.span() {
+            Some(span) => {
+                let line = text[..span.start].matches('\n').count() + 1;
+                format!("Line {}: {}", line, e.message())
+            }
+            None => e.message().to_string(),
+        })
+        .and_then(|profile| profile.validate().map(|_| profile))
+        .and_then(|profile| match credentials::password(&profile.uri) {
+            Some(_) => Err("Leave the password out of the URI; it is asked for when \
+                            connecting and kept in the encrypted credential file"
+                .to_string()),
+            None => Ok(profile),
+        })
+        .and_then(|profile| {
+            let name = profile.name.clone();
+            let mut profiles = ConnectionProfiles::load()?;
+            profiles.upsert(original.as_deref(), profile)?;
+            profiles
+                .save()
+                .map_err(|e| format!("Failed to save profiles: {}", e))?;
+            Ok((name, profiles))
+        });
+
+    match result {
+        Ok((name, profiles)) => {
+            state.set_profiles(profiles.profiles);
+            if let Some(index) = state.profiles.iter().position(|p| p.name == name) {
+                state.selected_profile_index = index;
+            }
+            state.set_notice(Some(format!("Saved profile '{}'", name)));
+        }
+        Err(message) => {
+            state.modal = Some(Modal::EditError {
+                edit: edit.with_text(text),
+                message,
+            });
+        }
+    }
+}
+
+fn delete_profile(state: &mut AppState, name: &str) {
+    let result = ConnectionProfiles::load().and_then(|mut profiles| {
+        profiles.remove(name);
+        profiles
+            .save()
+            .map_err(|e| format!("Failed to save profiles: {}", e))?;
+        Ok(profiles)
+    });
+
+    match result {
+        Ok(profiles) => {
+            state.set_profiles(profiles.profiles);
+            state.set_notice(Some(format!("Deleted profile '{}'", name)));
+        }
+        Err(e) => state.set_error(Some(e)),
+    }
+}
+
+fn handle_database_list_key(state: &mut AppState, ctx: &mut Context, key: KeyEvent) {
+    match key.code {
+        KeyCode::Char('q') => {
+            state.quit();
+        }
+        KeyCode::Down | KeyCode::Char('j') => {
+            state.select_next_db();
+        }
+        KeyCode::Up | KeyCode::Char('k') => {
+            state.select_prev_db();
+        }
+        KeyCode::Enter => {
+            if let Some(db_name) = state.get_selected_database().map(|db| db.name.clone()) {
+                load_collections(state, ctx, db_name, "Loading collections...");
+            }
+        }
+        KeyCode::Char('r') => {
+            load_databases(state, ctx, "Refreshing databases...");
+        }
+        KeyCode::Backspace => {
+            disconnect(state, ctx);
+        }
+        _ => {}
+    }
+}
+
+// back to the connection screen, closing the client and any SSH tunnel
+fn disconnect(state: &mut AppState, ctx: &mut Context) {
+    // its result carries the journal entry for undo
+    if ctx.tasks.is_writing() {
+        state.set_error(Some("Wait for the running write to finish".to_string()));
+        return;
+    }
+    ctx.tasks.abort();
+    state.set_loading(false);
+    ctx.conn_service.disconnect();
+    state.disconnect();
+    state.set_notice(Some("Disconnected".to_string()));
+}
+
+/// Reports an SSH tunnel that stopped under an open connection; queries
+/// through it fail from then on.
+pub fn check_tunnel(state: &mut AppState, ctx: &mut Context) {
+    if let Some(reason) = ctx.conn_service.closed_tunnel() {
+        state.set_tunnel_closed();
+        state.set_error(Some(reason));
+    }
+}
+
+fn handle_collection_list_key(state: &mut AppState, ctx: &mut Context, key: KeyEvent) {
+    match key.code {
+        KeyCode::Char('q') => {
+            state.quit();
+        }
+        KeyCode::Down | KeyCode::Char('j') => {
+            state.select_next_coll();
+        }
+        KeyCode::Up | KeyCode::Char('k') => {
+            state.select_prev_coll();
+        }
+        KeyCode::Enter => {
+            // Load documents for selected collection
+            if let Some(coll_name) = state.get_selected_collection().map(|c| c.name.clone()) {
+                state.reset_query();
+                if let Some(query) = state.query_for(&coll_name) {
+                    load_first(state, ctx, query, "Loading documents...");
+                }
+            }
+        }
+        KeyCode::Backspace => {
+            state.set_screen(Screen::DatabaseList);
+        }
+        KeyCode::Char('r') => {
+            // Refresh collections
+            if let Some(db_name) = state.current_database.clone() {
+                load_collections(state, ctx, db_name, "Refreshing collections...");
+            }
+        }
+        _ => {}
+    }
+}
+
+fn handle_document_view_key(state: &mut AppState, ctx: &mut Context, key: KeyEvent) {
+    if state.query_mode {
+        // Advanced query mode - JSON input
+        // Alt+Enter or Ctrl+J insert a line break, everything else editing
+        // related goes to the editor
+        match key.code {
+            KeyCode::Esc => {
+                state.exit_query_mode();
+                state.clear_query();
+            }
+            KeyCode::Enter if !key.modifiers.contains(KeyModifiers::ALT) => {
+                // Apply the JSON filter, sort or projection
+                match state.parse_query_input() {
+                    Ok(value) => {
+                        state.exit_query_mode();
+                        if let Some(mut query) = state.current_query() {
+                            match state.query_target {
+                                QueryTarget::Filter => query.filter = value,
+                                QueryTarget::Sort => query.sort = value,
+                                QueryTarget::Projection => query.projection = value,
+                            }
+                            load_first(state, ctx, query, "Running query...");
+                        }
+                    }
+                    Err(e) => {
+                        state.set_error(Some(e));
+                    }
+                }
+            }
+            _ => {
+                state.query_editor_mut().handle_key(key);
+            }
+        }
+    } else if state.page_input_mode {
+        // Jump to page - digits only
+        match key.code {
+            KeyCode::Char(c) => {
+                state.push_page_char(c);
+            }
+            KeyCode::Backspace => {
+                state.pop_page_char();
+            }
+            KeyCode::Esc => {
+                state.exit_page_input_mode();
+            }
+            KeyCode::Enter => {
+                state.exit_page_input_mode();
+                if let Some(page) = state.parse_page_input() {
+                    goto_page(state, ctx, page);
+                }
+            }
+            _ => {}
+        }
+    } else if state.filter_mode {
+        // Simple search mode - live filtering
+        match key.code {
+            KeyCode::Char(c) => {
+                state.push_filter_char(c);
+                apply_dynamic_filter(state, ctx);
+            }
+            KeyCode::Backspace => {
+                state.pop_filter_char();
+                apply_dynamic_filter(state, ctx);
+            }
+            KeyCode::Esc => {
+                state.exit_filter_mode();
+                state.filter_input.clear();
+                reload_with_filter(state, ctx, None, "Reloading documents...");
+            }
+            KeyCode::Enter => {
+                state.exit_filter_mode();
+            }
+            _ => {}
+        }
+    } else {
+        // Normal navigation mode
+        if state.content_focus && handle_content_key(state, key) {
+            return;
+        }
+        if state.grid_view && !state.content_focus && handle_grid_key(state, key) {
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char('q') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                state.quit();
+            }
+            KeyCode::Tab => {
+                state.toggle_content_focus();
+            }
+            KeyCode::Char('T') => {
+                state.toggle_grid_view();
+            }
+            KeyCode::Char('i' | 'e' | 'c' | 'd' | 'D' | 'u') if refuse_write(state) => {}
+            KeyCode::Char('i') => {
+                if let (Some(db_name), Some(coll_name)) =
+                    (&state.current_database, &state.current_collection)
+                {
+                    state.pending_edit = Some(ExternalEdit::insert(db_name, coll_name));
+                }
+            }
+            KeyCode::Char('e') => {
+                fetch_selected(state, ctx, FetchPurpose::Edit);
+            }
+            KeyCode::Char('c') => match state.get_selected_document() {
+                // without an _id it cannot be read again; copy it as shown
+                Some(document) if !document.contains_key("_id") => {
+                    open_clone_target(state, document.clone());
+                }
+                Some(_) => fetch_selected(state, ctx, FetchPurpose::Clone),
+                None => {}
+            },
+            KeyCode::Char('u') => {
+                if state.journal.is_empty() {
+                    state.set_notice(Some("Nothing to undo".to_string()));
+                } else {
+                    state.modal = Some(Modal::ConfirmUndo);
+                }
+            }
+            KeyCode::Char('d') => {
+                if let Some(id) = state
+                    .get_selected_document()
+                    .and_then(|document| document.get("_id"))
+                {
+                    let filter = doc! { "_id": id.clone() };
+                    count_for_delete(state, ctx, filter, false);
+                }
+            }
+            KeyCode::Char('D') => {
+                let filter = state.filter.clone().unwrap_or_default();
+                count_for_delete(state, ctx, filter, true);
+            }
+            KeyCode::Char('t') => {
+                state.toggle_content_view();
+                if state.content_view == ContentView::Tree {
+                    state.content_focus = true;
+                }
+            }
+            KeyCode::Char('f') => {
+                state.enter_filter_mode();
+            }
+            KeyCode::Char('/') => {
+                state.enter_query_mode(QueryTarget::Filter);
+            }
+            KeyCode::Char('s') => {
+                state.enter_query_mode(QueryTarget::Sort);
+            }
+            KeyCode::Char('o') => {
+                state.enter_query_mode(QueryTarget::Projection);
+            }
+            KeyCode::Char('m') => {
+                state.cycle_display_mode();
+                let mode = state.display_mode.label();
+                state.set_notice(Some(format!("Showing documents as {}", mode)));
+            }
+            KeyCode::Char('a') => {
+                state.aggregation.open(state.filter.as_ref());
+                state.set_screen(Screen::Aggregation);
+            }
+            KeyCode::Char('U') => {
+                state.update.open(state.filter.as_ref());
+                state.set_screen(Screen::Update);
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                state.select_next_doc();
+                extend_stream(state, ctx);
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                state.select_prev_doc();
+                extend_stream(state, ctx);
+            }
+            KeyCode::Char('S') => {
+                state.toggle_paging_mode();
+                reload_documents(state, ctx, "Reloading documents...");
+            }
+            KeyCode::PageDown => {
+                state.scroll_doc_down();
+            }
+            KeyCode::PageUp => {
+                state.scroll_doc_up();
+            }
+            KeyCode::Backspace => {
+                state.set_screen(Screen::CollectionList);
+            }
+            KeyCode::Esc => {
+                state.filter_input.clear();
+                reload_with_filter(state, ctx, None, "Reloading documents...");
+            }
+            KeyCode::Char('r') if state.paging_mode == PagingMode::Stream => {
+                reload_documents(state, ctx, "Refreshing documents...");
+            }
+            KeyCode::Char('g') | KeyCode::Home if state.paging_mode == PagingMode::Stream => {
+                stream_current(state, ctx, StreamFetch::First, "Loading first documents...");
+            }
+            KeyCode::Char('G') | KeyCode::End if state.paging_mode == PagingMode::Stream => {
+                stream_current(state, ctx, StreamFetch::Last, "Loading last documents...");
+            }
+            _ if state.paging_mode == PagingMode::Stream => {}
+            KeyCode::Char('r') => {
+                let page = state.current_page;
+                goto_page(state, ctx, page);
+            }
+            KeyCode::Char('n') | KeyCode::Right if state.current_page < state.last_page() => {
+                let page = state.current_page + 1;
+                goto_page(state, ctx, page);
+            }
+            KeyCode::Char('p') | KeyCode::Left if state.current_page > 0 => {
+                let page = state.current_page - 1;
+                goto_page(state, ctx, page);
+            }
+            KeyCode::Char('g') | KeyCode::Home => {
+                goto_page(state, ctx, 0);
+            }
+            KeyCode::Char('G') | KeyCode::End => {
+                let page = state.last_page();
+                goto_page(state, ctx, page);
+            }
+            KeyCode::Char(':') => {
+                state.enter_page_input_mode();
+            }
+            KeyCode::Char('+') => {
+                state.increase_page_size();
+                let page = state.current_page;
+                goto_page(state, ctx, page);
+            }
+            KeyCode::Char('-') => {
+                state.decrease_page_size();
+                let page = state.current_page;
+                goto_page(state, ctx, page);
+            }
+            _ => {}
+        }
+    }
+}
+
+// keys for the document pane when it has focus; returns false for keys the
+// list handles instead
+fn handle_content_key(state: &mut AppState, key: KeyEvent) -> bool {
+    const PAGE: usize = 10;
+
+    if state.content_view == ContentView::Text {
+        match key.code {
+            KeyCode::Down | KeyCode::Char('j') => state.scroll_doc_down(),
+            KeyCode::Up | KeyCode::Char('k') => state.scroll_doc_up(),
+            _ => return false,
+        }
+        return true;
+    }
+
+    let Some(doc) = state.documents.get(state.selected_doc_index) else {
+        return false;
+    };
+    let rows = state.tree.rows(doc).len();
+    let tree = &mut state.tree;
+
+    match key.code {
+        KeyCode::Down | KeyCode::Char('j') => tree.move_down(rows, 1),
+        KeyCode::Up | KeyCode::Char('k') => tree.move_up(1),
+        KeyCode::PageDown => tree.move_down(rows, PAGE),
+        KeyCode::PageUp => tree.move_up(PAGE),
+        KeyCode::Enter | KeyCode::Char(' ') => tree.toggle(doc),
+        KeyCode::Right | KeyCode::Char('l') => tree.expand(doc),
+        KeyCode::Left | KeyCode::Char('h') => tree.collapse(doc),
+        KeyCode::Char('E') => tree.expand_all(doc),
+        KeyCode::Char('C') => tree.collapse_all(),
+        _ => return false,
+    }
+    true
+}
+
+// column keys of the table view
+fn handle_grid_key(state: &mut AppState, key: KeyEvent) -> bool {
+    let grid = &mut state.grid;
+    match key.code {
+        KeyCode::Char(']') => grid.select_next_column(),
+        KeyCode::Char('[') => grid.select_prev_column(),
+        KeyCode::Char('>') => grid.resize_selected(2),
+        KeyCode::Char('<') => grid.resize_selected(-2),
+        KeyCode::Char('L') => grid.move_selected(1),
+        KeyCode::Char('H') => grid.move_selected(-1),
+        KeyCode::Char('x') => grid.hide_selected(),
+        KeyCode::Char('X') => grid.show_all(),
+        _ => return false,
+    }
+    true
+}
+
+fn handle_aggregation_key(state: &mut AppState, ctx: &mut Context, key: KeyEvent) {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+    match key.code {
+        KeyCode::Char('q') if ctrl => {
+            state.quit();
+            return;
+        }
+        KeyCode::PageDown => {
+            state.aggregation.scroll_preview_down();
+            return;
+        }
+        KeyCode::PageUp => {
+            state.aggregation.scroll_preview_up();
+            return;
+        }
+        _ => {}
+    }
+
+    match state.aggregation.focus {
+        AggregationFocus::Editor => match key.code {
+            KeyCode::Char('r') if ctrl => run_aggregation(state, ctx, None),
+            KeyCode::Char('s') if ctrl => state.aggregation.start_save(),
+            KeyCode::Char('o') if ctrl => match SavedPipelines::load() {
+                Ok(saved) => state.aggregation.show_saved(saved.pipelines),
+                Err(e) => state.set_error(Some(e)),
+            },
+            KeyCode::Tab => state.aggregation.toggle_focus(),
+            KeyCode::Esc => state.set_screen(Screen::DocumentView),
+            _ => {
+                state.aggregation.handle_editor_key(key);
+            }
+        },
+        AggregationFocus::Stages => match key.code {
+            KeyCode::Char('r') if ctrl => run_aggregation(state, ctx, None),
+            KeyCode::Down | KeyCode::Char('j') => state.aggregation.select_next_stage(),
+            KeyCode::Up | KeyCode::Char('k') => state.aggregation.select_prev_stage(),
+            KeyCode::Char(' ') => state.aggregation.toggle_selected_stage(),
+            KeyCode::Enter => {
+                let through = state.aggregation.selected_stage;
+                run_aggregation(state, ctx, Some(through));
+            }
+            KeyCode::Tab => state.aggregation.toggle_focus(),
+            KeyCode::Esc => state.set_screen(Screen::DocumentView),
+            _ => {}
+        },
+        AggregationFocus::SaveName => match key.code {
+            KeyCode::Char(c) => state.aggregation.save_name.push(c),
+            KeyCode::Backspace => {
+                state.aggregation.save_name.pop();
+            }
+            KeyCode::Enter => {
+                let name = state.aggregation.save_name.trim().to_string();
+                if name.is_empty() {
+                    return;
+                }
+                let result = SavedPipelines::load().and_then(|mut saved| {
+                    saved.upsert(name.clone(), state.aggregation.editor.text());
+                    saved
+                        .save()
+                        .map_err(|e| format!("Failed to save pipeline: {}", e))
+                });
+                match result {
+                    Ok(_) => state.set_notice(Some(format!("Saved pipeline '{}'", name))),
+                    Err(e) => state.set_error(Some(e)),
+                }
+                state.aggregation.focus = AggregationFocus::Editor;
+            }
+            KeyCode::Esc => state.aggregation.focus = AggregationFocus::Editor,
+            _ => {}
+        },
+        AggregationFocus::Saved => match key.code {
+            KeyCode::Down | KeyCode::Char('j') => state.aggregation.select_next_saved(),
+            KeyCode::Up | KeyCode::Char('k') => state.aggregation.select_prev_saved(),
+            KeyCode::Enter => state.aggregation.load_selected_saved(),
+            KeyCode::Char('d') => {
+                let mut saved = match SavedPipelines::load() {
+                    Ok(saved) => saved,
+                    Err(e) => {
+                        state.set_error(Some(e));
+                        return;
+                    }
+                };
+                saved.remove(state.aggregation.selected_saved);
+                if let Err(e) = saved.save() {
+                    state.set_error(Some(format!("Failed to save pipelines: {}", e)));
+                }
+                state.aggregation.show_saved(saved.pipelines);
+            }
+            KeyCode::Esc => state.aggregation.focus = AggregationFocus::Editor,
+            _ => {}
+        },
+    }
+}
+
+// runs the enabled stages up to `through` (or all of them) and shows the
+// first page of output
+fn run_aggregation(state: &mut AppState, ctx: &mut Context, through: Option<usize>) {
+    if let Some(e) = &state.aggregation.parse_error {
+        state.set_error(Some(e.clone()));
+        return;
+    }
+
+    let (Some(db_name), Some(coll_name), Some(query_service)) = (
+        state.current_database.clone(),
+        state.current_collection.clone(),
+        ctx.conn_service.query_service(),
+    ) else {
+        return;
+    };
+
+    let mut pipeline = state.aggregation.preview_pipeline(through);
+    pipeline.push(doc! { "$limit": state.page_size as i64 });
+    let skipped_writes = state.aggregation.has_write_stage(through);
+
+    state.start_loading("Running pipeline...");
+    if skipped_writes {
+        state.set_notice(Some(
+            "$out/$merge stages are skipped in previews".to_string(),
+        ));
+    }
+    ctx.tasks
+        .spawn_query(query_service, |query_service| async move {
+            let result = query_service
+                .aggregate(&db_name, &coll_name, pipeline)
+                .await;
+            TaskResult::AggregationPreview { through, result }
+        });
+}
+
+fn handle_update_key(state: &mut AppState, ctx: &mut Context, key: KeyEvent) {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+    match key.code {
+        KeyCode::Char('q') if ctrl => state.quit(),
+        KeyCode::Char('r') if ctrl => dry_run_update(state, ctx),
+        KeyCode::Char('x') if ctrl => {
+            if !refuse_write(state) {
+                confirm_update(state);
+            }
+        }
+        KeyCode::PageDown => state.update.scroll_preview_down(),
+        KeyCode::PageUp => state.update.scroll_preview_up(),
+        KeyCode::Tab => state.update.toggle_focus(),
+        KeyCode::Esc => state.set_screen(Screen::DocumentView),
+        _ => {
+            state.update.handle_editor_key(key);
+        }
+    }
+}
+
+// the services refuse writes on a read-only connection anyway; checking up
+// front saves writing an edit that could never be saved
+fn refuse_write(state: &mut AppState) -> bool {
+    if state.is_read_only() {
+        state.set_error(Some(
+            "Read-only connection: writes are disabled".to_string(),
+        ));
+    }
+    state.is_read_only()
+}
+
+// counts the matching documents and shows what the update would change in
+// a few of them
+fn dry_run_update(state: &mut AppState, ctx: &mut Context) {
+    let (filter, update) = match state.update.parse() {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            state.set_error(Some(e));
+            return;
+        }
+    };
+    let (Some(db_name), Some(coll_name), Some(query_service)) = (
+        state.current_database.clone(),
+        state.current_collection.clone(),
+        ctx.conn_service.query_service(),
+    ) else {
+        return;
+    };
+
+    let input = state.update.input();
+    let stages = update::preview_stages(&update);
+    state.update.clear_dry_run();
+    if let Err(note) = &stages {
+        state.update.sample_note = Some(note.clone());
+    }
+
+    state.start_loading("Running dry run...");
+    ctx.tasks
+        .spawn_query(query_service, |query_service| async move {
+            let result =
+                sample_update(&query_service, &db_name, &coll_name, filter, stages.ok()).await;
+            TaskResult::UpdateDryRun { input, result }
+        });
+}
+
+// runs `stages` over a sample of the documents matching `filter` without
+// writing, and diffs each document against its updated version
+async fn sample_update(
+    query_service: &QueryService,
+    db_name: &str,
+    coll_name: &str,
+    filter: Document,
+    stages: Option<Vec<Document>>,
+) -> Result<(u64, Vec<UpdateSample>), AppError> {
+    let matched = query_service
+        .count_documents(db_name, coll_name, Some(filter.clone()))
+        .await?;
+    let Some(stages) = stages else {
+        return Ok((matched, Vec::new()));
+    };
+
+    let sample = vec![
+        doc! { "$match": filter },
+        doc! { "$limit": update::SAMPLE_SIZE },
+    ];
+    let before = query_service.aggregate(db_name, coll_name, sample).await?;
+    let ids: Vec<Bson> = before
+        .iter()
+        .filter_map(|document| document.get("_id").cloned())
+        .collect();
+
+    let mut pipeline = vec![doc! { "$match": { "_id": { "$in": ids } } }];
+    pipeline.extend(stages);
+    let after = query_service
+        .aggregate(db_name, coll_name, pipeline)
+        .await?;
+
+    let samples = before
+        .iter()
+        .filter_map(|before| {
+            let id = before.get("_id")?;
+            let after = after.iter().find(|after| after.get("_id") == Some(id))?;
+            Some(UpdateSample {
+                id: id.clone(),
+                changes: diff::diff_documents(before, after),
+            })
+        })
+        .collect();
+    Ok((matched, samples))
+}
+
+// asks for confirmation of the update the last dry run was made for
+fn confirm_update(state: &mut AppState) {
+    let (Some(count), true) = (state.update.matched, state.update.is_dry_run_current()) else {
+        state.set_error(Some("Run a dry run with Ctrl+R first".to_string()));
+        return;
+    };
+    let (Ok((filter, update)), Some(database), Some(collection)) = (
+        state.update.parse(),
+        state.current_database.clone(),
+        state.current_collection.clone(),
+    ) else {
+        return;
+    };
+
+    state.modal = Some(Modal::ConfirmUpdate {
+        database,
+        collection,
+        filter,
+        update,
+        count,
+    });
+}
+
+fn update_documents(
+    state: &mut AppState,
+    ctx: &mut Context,
+    db_name: String,
+    coll_name: String,
+    filter: Document,
+    update: UpdateModifications,
+) {
+    let Some(crud_service) = ctx.conn_service.crud_service() else {
+        return;
+    };
+
+    spawn_write(state, ctx, "Updating documents...", async move {
+        let (previous, result) = crud_service
+            .update_documents(&db_name, &coll_name, filter, update, journal::MAX_DOCUMENTS)
+            .await;
+        TaskResult::DocumentsUpdated {
+            database: db_name,
+            collection: coll_name,
+            previous,
+            result,
+        }
+    });
+}
+
+/// Handles the text saved in the external editor for `edit`.
+pub fn handle_edit_result(
+    state: &mut AppState,
+    ctx: &mut Context,
+    edit: ExternalEdit,
+    result: std::io::Result<String>,
+) {
+    let text = match result {
+        Ok(text) => text,
+        Err(e) => {
+            state.set_error(Some(format!("Failed to run the editor: {}", e)));
+            return;
+        }
+    };
+
+    if let EditPurpose::Profile { original } = &edit.purpose {
+        let original = original.clone();
+        save_profile(state, edit, original, text);
+        return;
+    }
+
+    if external::is_blank(&text, "//") {
+        state.set_notice(Some("Nothing to save".to_string()));
+        return;
+    }
+
+    let document = match syntax::parse_document(&text) {
+        Ok(document) => document,
+        Err(e) => {
+            state.modal = Some(Modal::EditError {
+                edit: edit.with_text(text),
+                message: e.to_string(),
+            });
+            return;
+        }
+    };
+
+    match edit.purpose {
+        EditPurpose::Insert {
+            database,
+            collection,
+        } => insert_document(state, ctx, database, collection, document),
+        EditPurpose::Replace { ref original, .. } => {
+            if document.get("_id") != original.get("_id") {
+                state.modal = Some(Modal::EditError {
+                    edit: edit.with_text(text),
+                    message: "The _id field cannot be changed".to_string(),
+                });
+                return;
+            }
+
+            let changes = diff::diff_documents(original, &document);
+            if changes.is_empty() {
+                state.set_notice(Some("No changes".to_string()));
+                return;
+            }
+            state.modal = Some(Modal::ConfirmReplace {
+                edit: edit.with_text(text),
+                replacement: document,
+                changes,
+            });
+        }
+        EditPurpose::Profile { .. } => unreachable!("profiles are saved by save_profile"),
+    }
+}
+
+// reads the selected document again by _id, so the editor gets every field
+// even when a projection is active, and the version the conflict check
+// compares against is current
+fn fetch_selected(state: &mut AppState, ctx: &mut Context, purpose: FetchPurpose) {
+    let (Some(db_name), Some(coll_name), Some(query_service)) = (
+        state.current_database.clone(),
+        state.current_collection.clone(),
+        ctx.conn_service.query_service(),
+    ) else {
+        return;
+    };
+    let Some(document) = state.get_selected_document() else {
+        return;
+    };
+    let Some(id) = document.get("_id").cloned() else {
+        state.set_error(Some("The selected document has no _id".to_string()));
+        return;
+    };
+
+    state.start_loading("Loading document...");
+    ctx.tasks
+        .spawn_query(query_service, |query_service| async move {
+            let result = query_service.find_by_id(&db_name, &coll_name, id).await;
+            TaskResult::DocumentFetched {
+                database: db_name,
+                collection: coll_name,
+                purpose,
+                result,
+            }
+        });
+}
+
+// asks where to insert a copy of `document`, starting at the current
+// collection
+fn open_clone_target(state: &mut AppState, document: Document) {
+    let (Some(db_name), Some(coll_name)) = (&state.current_database, &state.current_collection)
+    else {
+        return;
+    };
+
+    let mut databases: Vec<String> = state.databases.iter().map(|db| db.name.clone()).collect();
+    if !databases.contains(db_name) {
+        databases.push(db_name.clone());
+    }
+    let mut collections: Vec<String> = state
+        .collections
+        .iter()
+        .map(|coll| coll.name.clone())
+        .collect();
+    if !collections.contains(coll_name) {
+        collections.push(coll_name.clone());
+    }
+
+    state.modal = Some(Modal::CloneTarget {
+        document,
+        database: databases
+            .iter()
+            .position(|name| name == db_name)
+            .unwrap_or(0),
+        databases,
+        collection: collections
+            .iter()
+            .position(|name| name == coll_name)
+            .unwrap_or(0),
+        collections,
+        choosing_collection: true,
+        loading: false,
+    });
+}
+
+// lists the collections of the database chosen in the clone target dialog
+fn load_clone_collections(state: &mut AppState, ctx: &mut Context, database: String) {
+    if let Some(query_service) = ctx.conn_service.query_service() {
+        state.start_loading("Loading collections...");
+        ctx.tasks.spawn(async move {
+            let result = query_service.list_collections(&database).await;
+            TaskResult::CloneCollectionsLoaded { database, result }
+        });
+    }
+}
+
+// counts what `filter` would delete so the confirmation dialog can show it
+fn count_for_delete(state: &mut AppState, ctx: &mut Context, filter: Document, bulk: bool) {
+    let (Some(db_name), Some(coll_name), Some(query_service)) = (
+        state.current_database.clone(),
+        state.current_collection.clone(),
+        ctx.conn_service.query_service(),
+    ) else {
+        return;
+    };
+
+    state.start_loading("Counting documents...");
+    ctx.tasks
+        .spawn_query(query_service, |query_service| async move {
+            let result = query_service
+                .count_documents(&db_name, &coll_name, Some(filter.clone()))
+                .await;
+            TaskResult::DeleteCounted {
+                database: db_name,
+                collection: coll_name,
+                filter,
+                bulk,
+                result,
+            }
+        });
+}
+
+fn delete_documents(
+    state: &mut AppState,
+    ctx: &mut Context,
+    db_name: String,
+    coll_name: String,
+    filter: Document,
+) {
+    let Some(crud_service) = ctx.conn_service.crud_service() else {
+        return;
+    };
+
+    spawn_write(state, ctx, "Deleting...", async move {
+        let (previous, result) = crud_service
+            .delete_documents(&db_name, &coll_name, filter, journal::MAX_DOCUMENTS)
+            .await;
+        TaskResult::DocumentsDeleted {
+            database: db_name,
+            collection: coll_name,
+            previous,
+            result,
+        }
+    });
+}
+
+fn replace_document(
+    state: &mut AppState,
+    ctx: &mut Context,
+    db_name: String,
+    coll_name: String,
+    original: Document,
+    replacement: Document,
+) {
+    let Some(crud_service) = ctx.conn_service.crud_service() else {
+        return;
+    };
+
+    spawn_write(state, ctx, "Saving document...", async move {
+        let result = crud_service
+            .replace_document(&db_name, &coll_name, &original, replacement)
+            .await;
+        TaskResult::DocumentReplaced {
+            database: db_name,
+            collection: coll_name,
+            original,
+            result,
+        }
+    });
+}
+
+fn insert_document(
+    state: &mut AppState,
+    ctx: &mut Context,
+    db_name: String,
+    coll_name: String,
+    document: Document,
+) {
+    let Some(crud_service) = ctx.conn_service.crud_service() else {
+        return;
+    };
+
+    spawn_write(state, ctx, "Inserting document...", async move {
+        let result = crud_service
+            .insert_document(&db_name, &coll_name, document)
+            .await;
+        TaskResult::DocumentInserted {
+            database: db_name,
+            collection: coll_name,
+            result,
+        }
+    });
+}
+
+// reverts the newest journal entry; it is only dropped from the journal
+// once the revert succeeded
+fn undo_last_write(state: &mut AppState, ctx: &mut Context) {
+    let (Some(entry), Some(crud_service)) = (
+        state.journal.last().cloned(),
+        ctx.conn_service.crud_service(),
+    ) else {
+        return;
+    };
+
+    spawn_write(state, ctx, "Undoing...", async move {
+        let JournalEntry {
+            database,
+            collection,
+            change,
+            ..
+        } = entry;
+        let result = match change {
+            JournalChange::Inserted(id) => {
+                crud_service
+                    .delete_document(&database, &collection, doc! { "_id": id })
+                    .await
+            }
+            JournalChange::Restore(documents) => {
+                crud_service
+                    .restore_documents(&database, &collection, documents)
+                    .await
+            }
+        };
+        TaskResult::Undone(result)
+    });
+}
+
+fn record_write(
+    state: &mut AppState,
+    database: String,
+    collection: String,
+    label: String,
+    change: JournalChange,
+) {
+    state.journal.push(JournalEntry {
+        database,
+        collection,
+        label,
+        change,
+    });
+}
+
+/// Applies a finished background task to the UI state.
+pub fn handle_task_result(state: &mut AppState, ctx: &mut Context, result: TaskResult) {
+    // a finished write may have started the read queued behind it
+    state.set_loading(ctx.tasks.is_busy());
+    if !ctx.tasks.is_writing() && state.write_message.take().is_some() {
+        // drops what was said about the write while it ran
+        state.set_notice(None);
+    }
+
+    match result {
+        TaskResult::Connected {
+            uri,
+            profile,
+            remember,
+            result,
+        } => match result {
+            Ok((client, server_info, tunnel)) => {
+                if let Some((key, password)) = remember
+                    && let Some(store) = ctx.credentials.as_mut()
+                    && let Err(e) = store.insert(key, password)
+                {
+                    state.set_error(Some(format!("Failed to save the password: {}", e)));
+                }
+
+                // Save to history; profiles are kept apart from it
+                if profile.is_none() {
+                    ctx.history.add_uri(uri);
+                    let _ = ctx.history.save();
+                    state.set_connection_history(ctx.history.uris.clone());
+                }
+
+                // any member of a replica set can make it production
+                let read_only = std::iter::once(&server_info.host)
+                    .chain(&server_info.hosts)
+                    .any(|host| state.config.is_read_only_host(host))
+                    || profile.as_ref().is_some_and(|profile| profile.read_only);
+                ctx.conn_service
+                    .attach(client, server_info.clone(), read_only, tunnel);
+                state.set_connection(server_info, read_only, profile);
+                // entries refer to documents on the previous server
+                state.journal.clear();
+                load_databases(state, ctx, "Loading databases...");
+            }
+            Err(e) => {
+                state.set_error(Some(format!("Connection failed: {}", e)));
+            }
+        },
+        TaskResult::UriValidated { uri, result } => match result {
+            Ok(()) => connect(state, ctx, uri, None, true),
+            Err(e) => state.set_error(Some(e.to_string())),
+        },
+        TaskResult::CredentialsUnlocked {
+            uri,
+            profile,
+            result,
+        } => match result {
+            Ok(store) => {
+                ctx.credentials = Some(store);
+                connect(state, ctx, uri, profile, true);
+            }
+            Err(e) => {
+                state.modal = Some(Modal::Passphrase {
+                    input: String::new(),
+                    first: None,
+                    creating: false,
+                    error: Some(e.to_string()),
+                    uri,
+                    profile,
+                });
+            }
+        },
+        TaskResult::DatabasesLoaded(result) => match result {
+            Ok(databases) => {
+                state.set_databases(databases);
+                state.set_screen(Screen::DatabaseList);
+                // the profile's default database opens straight away
+                if let Some(db_name) = state.pending_database.take() {
+                    match state.databases.iter().position(|db| db.name == db_name) {
+                        Some(index) => {
+                            state.selected_db_index = index;
+                            load_collections(state, ctx, db_name, "Loading collections...");
+                        }
+                        None => {
+                            state.set_notice(Some(format!("Database '{}' not found", db_name)));
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                state.set_error(Some(format!("Failed to load databases: {}", e)));
+            }
+        },
+        TaskResult::CollectionsLoaded { database, result } => match result {
+            Ok(collections) => {
+                state.current_database = Some(database);
+                state.set_collections(collections);
+                state.set_screen(Screen::CollectionList);
+            }
+            Err(e) => {
+                state.set_error(Some(format!("Failed to load collections: {}", e)));
+            }
+        },
+        TaskResult::CloneCollectionsLoaded { database, result } => {
+            // the dialog may have been closed or moved to another database
+            let Some(Modal::CloneTarget {
+                databases,
+                database: chosen,
+                collections,
+                collection,
+                loading,
+                ..
+            }) = state.modal.as_mut()
+            else {
+                return;
+            };
+            if databases[*chosen] != database {
+                return;
+            }
+            *loading = false;
+            match result {
+                Ok(loaded) => {
+                    // keep the collection name, which is created if the
+                    // database has none of that name
+                    let name = collections[*collection].clone();
+                    let mut names: Vec<String> = loaded.into_iter().map(|coll| coll.name).collect();
+                    if !names.contains(&name) {
+                        names.push(name.clone());
+                    }
+                    *collection = names.iter().position(|n| *n == name).unwrap_or(0);
+                    *collections = names;
+                }
+                Err(e) => {
+                    state.set_error(Some(format!("Failed to load collections: {}", e)));
+                }
+            }
+        }
+        TaskResult::AggregationPreview { through, result } => match result {
+            Ok(documents) => {
+                state.aggregation.set_preview(through, documents);
+            }
+            Err(e) => {
+                state.set_error(Some(format!("Aggregation failed: {}", e)));
+            }
+        },
+        TaskResult::DocumentInserted {
+            database,
+            collection,
+            result,
+        } => match result {
+            Ok(id) => {
+                reload_documents(state, ctx, "Reloading documents...");
+                let shown = syntax::to_compact_string(&id, DisplayMode::Shell);
+                let elsewhere = state.current_database.as_ref() != Some(&database)
+                    || state.current_collection.as_ref() != Some(&collection);
+                if elsewhere {
+                    state.set_notice(Some(format!(
+                        "Inserted document {} into {}.{}",
+                        shown, database, collection
+                    )));
+                } else {
+                    state.set_notice(Some(format!("Inserted document {}", shown)));
+                }
+                let label = format!("insert of {}", shown);
+                record_write(
+                    state,
+                    database,
+                    collection,
+                    label,
+                    JournalChange::Inserted(id),
+                );
+            }
+            Err(e) => {
+                state.set_error(Some(format!("Insert failed: {}", e)));
+            }
+        },
+        TaskResult::DocumentFetched {
+            database,
+            collection,
+            purpose,
+            result,
+        } => match result {
+            Ok(Some(document)) => match purpose {
+                FetchPurpose::Edit => {
+                    state.pending_edit =
+                        Some(ExternalEdit::replace(&database, &collection, document));
+                }
+                FetchPurpose::Clone => open_clone_target(state, document),
+            },
+            Ok(None) => {
+                state.set_error(Some("The document no longer exists".to_string()));
+            }
+            Err(e) => {
+                state.set_error(Some(format!("Failed to load document: {}", e)));
+            }
+        },
+        TaskResult::DocumentReplaced {
+            database,
+            collection,
+            original,
+            result,
+        } => match result {
+            Ok(()) => {
+                reload_documents(state, ctx, "Reloading documents...");
+                state.set_notice(Some("Document updated".to_string()));
+                let id = original.get("_id").cloned().unwrap_or(Bson::Null);
+                let id = syntax::to_compact_string(&id, DisplayMode::Shell);
+                let change = JournalChange::Restore(vec![original]);
+                record_write(
+                    state,
+                    database,
+                    collection,
+                    format!("edit of {}", id),
+                    change,
+                );
+            }
+            Err(e) => {
+                state.set_error(Some(format!("Update failed: {}", e)));
+            }
+        },
+        TaskResult::DeleteCounted {
+            database,
+            collection,
+            filter,
+            bulk,
+            result,
+        } => match result {
+            Ok(0) => {
+                state.set_notice(Some("No documents to delete".to_string()));
+            }
+            Ok(count) => {
+                state.modal = Some(Modal::ConfirmDelete {
+                    database,
+                    collection,
+                    filter,
+                    count,
+                    bulk,
+                    typed: String::new(),
+                });
+            }
+            Err(e) => {
+                state.set_error(Some(format!("Failed to count documents: {}", e)));
+            }
+        },
+        TaskResult::DocumentsDeleted {
+            database,
+            collection,
+            previous,
+            result,
+        } => match (result, previous) {
+            (Ok(deleted), Some(previous)) => {
+                reload_documents(state, ctx, "Reloading documents...");
+                state.set_notice(Some(format!(
+                    "Deleted {} document(s); 'u' to undo",
+                    deleted
+                )));
+                let label = format!("delete of {} document(s)", deleted);
+                record_write(
+                    state,
+                    database,
+                    collection,
+                    label,
+                    JournalChange::Restore(previous),
+                );
+            }
+            (Ok(deleted), None) => {
+                reload_documents(state, ctx, "Reloading documents...");
+                state.set_notice(Some(format!(
+                    "Deleted {} document(s); too many to undo",
+                    deleted
+                )));
+            }
+            // the delete may have removed some documents before failing
+            (Err(e), Some(previous)) if !previous.is_empty() => {
+                reload_documents(state, ctx, "Reloading documents...");
+                let label = format!("failed delete of {} document(s)", previous.len());
+                record_write(
+                    state,
+                    database,
+                    collection,
+                    label,
+                    JournalChange::Restore(previous),
+                );
+                state.set_error(Some(format!(
+                    "Delete failed: {}; 'u' restores any documents it removed",
+                    e
+                )));
+            }
+            (Err(e), _) => {
+                state.set_error(Some(format!("Delete failed: {}", e)));
+            }
+        },
+        TaskResult::UpdateDryRun { input, result } => match result {
+            Ok((matched, samples)) => {
+                state.update.set_dry_run(input, matched, samples);
+            }
+            Err(e) => {
+                state.set_error(Some(format!("Dry run failed: {}", e)));
+            }
+        },
+        TaskResult::DocumentsUpdated {
+            database,
+            collection,
+            previous,
+            result,
+        } => match result {
+            Ok((matched, modified)) => {
+                state.update.clear_dry_run();
+                reload_documents(state, ctx, "Reloading documents...");
+                state.set_notice(Some(format!(
+                    "Matched {} and modified {} document(s)",
+                    matched, modified
+                )));
+                if let Some(previous) = previous.filter(|_| modified > 0) {
+                    let label = format!("update of {} document(s)", modified);
+                    let change = JournalChange::Restore(previous);
+                    record_write(state, database, collection, label, change);
+                }
+            }
+            // the update may have changed some documents before failing
+            Err(e) => match previous.filter(|previous| !previous.is_empty()) {
+                Some(previous) => {
+                    reload_documents(state, ctx, "Reloading documents...");
+                    let label = format!("failed update of {} document(s)", previous.len());
+                    let change = JournalChange::Restore(previous);
+                    record_write(state, database, collection, label, change);
+                    state.set_error(Some(format!(
+                        "Update failed: {}; 'u' restores any documents it changed",
+                        e
+                    )));
+                }
+                None => state.set_error(Some(format!("Update failed: {}", e))),
+            },
+        },
+        TaskResult::Undone(result) => match result {
+            Ok(_) => {
+                reload_documents(state, ctx, "Reloading documents...");
+                if let Some(entry) = state.journal.pop() {
+                    state.set_notice(Some(format!("Undid {}", entry.label)));
+                }
+            }
+            Err(e) => {
+                state.set_error(Some(format!("Undo failed: {}", e)));
+            }
+        },
+        TaskResult::StreamBatchLoaded {
+            query,
+            fetch,
+            result,
+        } => match result {
+            Ok(batch) => {
+                state.commit_query(query);
+                state.apply_stream_batch(fetch, batch);
+                state.set_screen(Screen::DocumentView);
+            }
+            Err(e) => {
+                state.set_error(Some(format!("Failed to load documents: {}", e)));
+            }
+        },
+        TaskResult::DocumentsLoaded {
+            query,
+            page,
+            result,
+        } => match result {
+            Ok(query_result) => {
+                state.commit_query(query);
+                state.set_query_result(page, query_result);
+                state.set_screen(Screen::DocumentView);
+            }
+            Err(e) => {
+                state.set_error(Some(format!("Failed to load documents: {}", e)));
+            }
+        },
+    }
+}
+
+fn load_databases(state: &mut AppState, ctx: &mut Context, message: &str) {
+    if let Some(query_service) = ctx.conn_service.query_service() {
+        state.start_loading(message);
+        ctx.tasks.spawn(async move {
+            TaskResult::DatabasesLoaded(query_service.list_databases().await)
+        });
+    }
+}
+
+fn load_collections(state: &mut AppState, ctx: &mut Context, database: String, message: &str) {
+    if let Some(query_service) = ctx.conn_service.query_service() {
+        state.start_loading(message);
+        ctx.tasks.spawn(async move {
+            let result = query_service.list_collections(&database).await;
+            TaskResult::CollectionsLoaded { database, result }
+        });
+    }
+}
+
+fn load_page(
+    state: &mut AppState,
+    ctx: &mut Context,
+    mut query: QueryParams,
+    page: usize,
+    message: &str,
+) {
+    let Some(query_service) = ctx.conn_service.query_service() else {
+        return;
+    };
+
+    query.skip = (page * state.page_size) as u64;
+    query.limit = state.page_size as i64;
+    state.start_loading(message);
+    ctx.tasks
+        .spawn_query(query_service, |query_service| async move {
+            let result = query_service.find_page(&query).await;
+            TaskResult::DocumentsLoaded {
+                query,
+                page,
+                result,
+            }
+        });
+}
+
+/// Loads the first page (or streamed batch) of `query`, which becomes the
+/// active query once the documents arrive.
+fn load_first(state: &mut AppState, ctx: &mut Context, query: QueryParams, message: &str) {
+    match state.paging_mode {
+        PagingMode::Pages => load_page(state, ctx, query, 0, message),
+        PagingMode::Stream => load_stream(state, ctx, query, StreamFetch::First, message),
+    }
+}
+
+fn reload_documents(state: &mut AppState, ctx: &mut Context, message: &str) {
+    if let Some(query) = state.current_query() {
+        load_first(state, ctx, query, message);
+    }
+}
+
+fn reload_with_filter(
+    state: &mut AppState,
+    ctx: &mut Context,
+    filter: Option<Document>,
+    message: &str,
+) {
+    if let Some(mut query) = state.current_query() {
+        query.filter = filter;
+        load_first(state, ctx, query, message);
+    }
+}
+
+// loads another page of the current query
+fn goto_page(state: &mut AppState, ctx: &mut Context, page: usize) {
+    if let Some(query) = state.current_query() {
+        let message = format!("Loading page {}...", page + 1);
+        load_page(state, ctx, query, page, &message);
+    }
+}
+
+// requests the next or previous batch once the selection nears either end
+// of the streamed window
+fn extend_stream(state: &mut AppState, ctx: &mut Context) {
+    if ctx.tasks.is_busy() {
+        return;
+    }
+    if let Some(fetch) = state.pending_stream_fetch() {
+        stream_current(state, ctx, fetch, "Loading more documents...");
+    }
+}
+
+fn stream_current(state: &mut AppState, ctx: &mut Context, fetch: StreamFetch, message: &str) {
+    if let Some(query) = state.current_query() {
+        load_stream(state, ctx, query, fetch, message);
+    }
+}
+
+fn load_stream(
+    state: &mut AppState,
+    ctx: &mut Context,
+    mut query: QueryParams,
+    fetch: StreamFetch,
+    message: &str,
+) {
+    let Some(query_service) = ctx.conn_service.query_service() else {
+        return;
+    };
+
+    // skip positions count from the end the stream started at, the only
+    // one they are exact for
+    let mut limit = state.page_size;
+    let keyset = match fetch {
+        StreamFetch::First => Keyset::First,
+        StreamFetch::Last => Keyset::Last,
+        StreamFetch::Next if state.stream_by_offset && state.stream_from_end => {
+            let after = state.stream_after();
+            limit = limit.min(after);
+            if limit == 0 {
+                return;
+            }
+            Keyset::OffsetFromEnd((after - limit) as u64)
+        }
+        StreamFetch::Next if state.stream_by_offset => {
+            Keyset::Offset((state.stream_offset + state.documents.len()) as u64)
+        }
+        StreamFetch::Previous if state.stream_by_offset && state.stream_from_end => {
+            Keyset::OffsetFromEnd((state.stream_after() + state.documents.len()) as u64)
+        }
+        StreamFetch::Previous if state.stream_by_offset => {
+            let start = state.stream_offset.saturating_sub(state.page_size);
+            limit = state.stream_offset - start;
+            if limit == 0 {
+                return;
+            }
+            Keyset::Offset(start as u64)
+        }
+        StreamFetch::Next => match state.documents.last().and_then(|doc| doc.get("_id")) {
+            Some(id) => Keyset::After(id.clone()),
+            None => return,
+        },
+        StreamFetch::Previous => match state.documents.first().and_then(|doc| doc.get("_id")) {
+            Some(id) => Keyset::Before(id.clone()),
+            None => return,
+        },
+    };
+
+    query.limit = limit as i64;
+    state.start_loading(message);
+    ctx.tasks
+        .spawn_query(query_service, |query_service| async move {
+            let result = query_service.find_stream_batch(&query, keyset).await;
+            TaskResult::StreamBatchLoaded {
+                query,
+                fetch,
+                result,
+            }
+        });
+}
+
+// live search across all fields for the `f` search mode
+fn apply_dynamic_filter(state: &mut AppState, ctx: &mut Context) {
+    if state.filter_input.is_empty() {
+        reload_with_filter(state, ctx, None, "Reloading documents...");
+        return;
+    }
+
+    let (Some(mut query), Some(query_service)) =
+        (state.current_query(), ctx.conn_service.query_service())
+    else {
+        return;
+    };
+
+    let term = state.filter_input.clone();
+    let paging_mode = state.paging_mode;
+    state.start_loading("Searching...");
+    ctx.tasks
+        .spawn_query(query_service, |query_service| async move {
+            match query_service
+                .search_filter(&query.database, &query.collection, &term)
+                .await
+            {
+                Ok(filter) => query.filter = filter,
+                Err(e) => {
+                    return TaskResult::DocumentsLoaded {
+                        query,
+                        page: 0,
+                        result: Err(e),
+                    };
+                }
+            }
+
+            match paging_mode {
+                PagingMode::Pages => TaskResult::DocumentsLoaded {
+                    result: query_service.find_page(&query).await,
+                    query,
+                    page: 0,
+                },
+                PagingMode::Stream => TaskResult::StreamBatchLoaded {
+                    result: query_service.find_stream_batch(&query, Keyset::First).await,
+                    query,
+                    fetch: StreamFetch::First,
+                },
+            }
+        });
+}
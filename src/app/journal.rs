@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+
+use mongodb::bson::{Bson, Document};
+
+/// Write operations kept for undo.
+pub const MAX_ENTRIES: usize = 20;
+/// Previous document versions kept across all entries; a single write
+/// touching more documents than this cannot be undone.
+pub const MAX_DOCUMENTS: usize = 1000;
+
+/// How to revert a write.
+#[derive(Debug, Clone)]
+pub enum JournalChange {
+    /// Delete the inserted document with this `_id`.
+    Inserted(Bson),
+    /// Write these versions back, re-inserting deleted documents.
+    Restore(Vec<Document>),
+}
+
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub database: String,
+    pub collection: String,
+    // what was done, e.g. "delete of 3 document(s)"
+    pub label: String,
+    pub change: JournalChange,
+}
+
+impl JournalEntry {
+    fn document_count(&self) -> usize {
+        match &self.change {
+            JournalChange::Inserted(_) => 0,
+            JournalChange::Restore(documents) => documents.len(),
+        }
+    }
+}
+
+/// Writes made in this session, newest last, so they can be undone one at
+/// a time.
+#[derive(Debug, Default)]
+pub struct Journal {
+    entries: VecDeque<JournalEntry>,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `entry`, dropping the oldest entries to stay within
+    /// `MAX_ENTRIES` and `MAX_DOCUMENTS`.
+    pub fn push(&mut self, entry: JournalEntry) {
+        self.entries.push_back(entry);
+        while self.entries.len() > MAX_ENTRIES || self.document_count() > MAX_DOCUMENTS {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn last(&self) -> Option<&JournalEntry> {
+        self.entries.back()
+    }
+
+    pub fn pop(&mut self) -> Option<JournalEntry> {
+        self.entries.pop_back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn document_count(&self) -> usize {
+        self.entries.iter().map(JournalEntry::document_count).sum()
+    }
+}
@@ -1,2 +1,14 @@
+pub mod aggregation;
+pub mod connection_form;
+pub mod diff;
+pub mod editor;
+pub mod external;
+pub mod grid;
+pub mod handler;
+pub mod journal;
+pub mod modal;
+pub mod screen;
 pub mod state;
-pub mod screen;
\ No newline at end of file
+pub mod task;
+pub mod tree;
+pub mod update;
@@ -0,0 +1,74 @@
+use mongodb::{bson::Document, options::UpdateModifications};
+
+use super::{diff::FieldChange, external::ExternalEdit};
+use crate::config::ConnectionProfile;
+
+/// Dialog shown on top of the current screen; it receives all key presses
+/// until closed.
+#[derive(Debug, Clone)]
+pub enum Modal {
+    /// Text saved in the external editor did not parse. It can be opened
+    /// again as it was left.
+    EditError { edit: ExternalEdit, message: String },
+    /// Deletion of the documents matching `filter`. With `bulk` set the
+    /// collection name has to be typed into `typed` before it can run.
+    ConfirmDelete {
+        database: String,
+        collection: String,
+        filter: Document,
+        count: u64,
+        bulk: bool,
+        typed: String,
+    },
+    /// `update` about to run on the `count` documents matching `filter`, as
+    /// found by the dry run.
+    ConfirmUpdate {
+        database: String,
+        collection: String,
+        filter: Document,
+        update: UpdateModifications,
+        count: u64,
+    },
+    /// Picks where a copy of `document` is inserted, from the loaded
+    /// databases and the collections of the one chosen.
+    CloneTarget {
+        document: Document,
+        databases: Vec<String>,
+        database: usize,
+        collections: Vec<String>,
+        collection: usize,
+        // whether ←/→ change the collection rather than the database
+        choosing_collection: bool,
+        // set while the collections of a newly chosen database are listed
+        loading: bool,
+    },
+    /// Undo of the last write in the journal.
+    ConfirmUndo,
+    /// Master passphrase that unlocks the credential file before connecting
+    /// to `uri`. With `creating` set the file does not exist yet and the
+    /// passphrase is typed twice, the first time kept in `first`.
+    Passphrase {
+        input: String,
+        first: Option<String>,
+        creating: bool,
+        error: Option<String>,
+        uri: String,
+        profile: Option<ConnectionProfile>,
+    },
+    /// Password for `key` (`user@hosts`), missing from both `uri` and the
+    /// credential file.
+    Password {
+        key: String,
+        input: String,
+        uri: String,
+        profile: Option<ConnectionProfile>,
+    },
+    /// Deletion of the connection profile called `name`.
+    ConfirmDeleteProfile { name: String },
+    /// An edited document waiting to be written, with the fields it changes.
+    ConfirmReplace {
+        edit: ExternalEdit,
+        replacement: Document,
+        changes: Vec<FieldChange>,
+    },
+}
@@ -1,7 +1,9 @@
-#[derive(Debug,Clone,PartialEq)]
-pub enum Screen{
+#[derive(Debug, Clone, PartialEq)]
+pub enum Screen {
     DatabaseList,
     CollectionList,
     DocumentView,
     Connection,
-}
\ No newline at end of file
+    Aggregation,
+    Update,
+}
@@ -1,13 +1,94 @@
-use std::fmt::format;
+use std::time::Duration;
+
+use super::{
+    aggregation::AggregationState, connection_form::ConnectionForm, editor::TextEditor,
+    external::ExternalEdit, grid::GridState, journal::Journal, modal::Modal, screen::Screen,
+    tree::TreeState, update::UpdateState,
+};
+use crate::{
+    config::ConnectionProfile,
+    models::{
+        AppConfig, CollectionInfo, DatabaseInfo, QueryParams, QueryResult, ServerInfo, StreamBatch,
+    },
+    syntax::{self, DisplayMode},
+};
+use mongodb::bson::{Bson, Document};
+
+// page sizes cycled through with +/- in the document view
+const PAGE_SIZES: [usize; 6] = [10, 20, 50, 100, 200, 500];
+
+// how close the selection gets to the end of a streamed window before the
+// next batch is requested
+const STREAM_PREFETCH: usize = 5;
+
+/// How the selected document is shown in the document view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentView {
+    /// Pretty-printed text in the current display mode.
+    Text,
+    /// Collapsible tree of fields.
+    Tree,
+}
+
+/// How the document view moves through a result set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PagingMode {
+    /// Fixed pages fetched with skip/limit.
+    Pages,
+    /// A sliding window over the results in `_id` order, extended in batches
+    /// as the selection nears either end.
+    Stream,
+}
+
+/// Which batch of a streamed result set a fetch returns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamFetch {
+    First,
+    Last,
+    Next,
+    Previous,
+}
+
+/// Part of the query edited in advanced query mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueryTarget {
+    Filter,
+    Sort,
+    Projection,
+}
+
+impl QueryTarget {
+    pub fn label(&self) -> &'static str {
+        match self {
+            QueryTarget::Filter => "Filter",
+            QueryTarget::Sort => "Sort",
+            QueryTarget::Projection => "Projection",
+        }
+    }
+}
 
-use super::screen::Screen;
-use crate::models::{CollectionInfo, DatabaseInfo, ServerInfo};
-use mongodb::bson::Document;
+/// Part of the connection screen that receives keys.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionFocus {
+    Input,
+    Profiles,
+    History,
+}
 
 #[derive(Debug, Clone)]
 pub struct ConnectionState {
-    pub uri: String,
     pub server_info: ServerInfo,
+    pub read_only: bool,
+    // profile the connection was opened with
+    pub profile: Option<ConnectionProfile>,
+    pub tunnel: Option<TunnelStatus>,
+}
+
+/// SSH tunnel a connection goes through, as shown in the header.
+#[derive(Debug, Clone)]
+pub struct TunnelStatus {
+    pub label: String,
+    pub open: bool,
 }
 
 #[derive(Debug)]
@@ -20,9 +101,42 @@ pub struct AppState {
     pub documents: Vec<Document>,
     pub current_page: usize,
     pub page_size: usize,
+    pub total_count: u64,
+    pub query_time: Option<Duration>,
+    pub page_input: String,
+    pub page_input_mode: bool,
+    pub paging_mode: PagingMode,
+    pub display_mode: DisplayMode,
+    pub content_view: ContentView,
+    // whether navigation keys move inside the document instead of the list
+    pub content_focus: bool,
+    pub tree: TreeState,
+    // documents as a table instead of a list of ids
+    pub grid_view: bool,
+    pub grid: GridState,
+    // position of documents[0] in the streamed result set
+    pub stream_offset: usize,
+    pub stream_has_before: bool,
+    pub stream_has_after: bool,
+    // read further batches with `skip`, after `_id`s of mixed types ended a
+    // keyset batch early
+    pub stream_by_offset: bool,
+    // the stream started at the last document, so positions are only exact
+    // counted from the end; stream_offset rests on an estimated total
+    pub stream_from_end: bool,
+    // first and last `_id` in `_id` order when the stream started
+    pub stream_first_id: Option<Bson>,
+    pub stream_last_id: Option<Bson>,
     pub filter: Option<Document>,
+    pub sort: Option<Document>,
+    pub projection: Option<Document>,
     pub loading: bool,
+    pub loading_message: String,
+    // message of the running write, which cannot be cancelled
+    pub write_message: Option<String>,
+    pub spinner_frame: usize,
     pub error: Option<String>,
+    pub notice: Option<String>,
     pub should_quit: bool,
     pub selected_db_index: usize,
     pub selected_coll_index: usize,
@@ -30,14 +144,30 @@ pub struct AppState {
     pub selected_doc_index: usize,
     pub doc_scroll_offset: usize,
     pub connection_input: String,
-    pub input_mode: bool,
+    // the URI split into fields, while the form replaces the URI input
+    pub connection_form: Option<ConnectionForm>,
     pub filter_input: String,
     pub filter_mode: bool,
     pub query_mode: bool,
-    pub query_input: String,
+    pub query_target: QueryTarget,
+    pub query_input: TextEditor,
+    pub sort_input: TextEditor,
+    pub projection_input: TextEditor,
     pub connection_history: Vec<String>,
     pub selected_history_index: usize,
-    pub show_history: bool,
+    pub connection_focus: ConnectionFocus,
+    pub profiles: Vec<ConnectionProfile>,
+    pub selected_profile_index: usize,
+    // database to open once the databases of a new connection are loaded
+    pub pending_database: Option<String>,
+    pub config: AppConfig,
+    pub aggregation: AggregationState,
+    pub update: UpdateState,
+    // writes made in this session, for undo
+    pub journal: Journal,
+    pub modal: Option<Modal>,
+    // document to open in the external editor on the next loop iteration
+    pub pending_edit: Option<ExternalEdit>,
 }
 
 impl AppState {
@@ -50,10 +180,34 @@ impl AppState {
             collections: Vec::new(),
             documents: Vec::new(),
             current_page: 0,
-            page_size: 20,
+            page_size: AppConfig::default().default_page_size,
+            total_count: 0,
+            query_time: None,
+            page_input: String::new(),
+            page_input_mode: false,
+            paging_mode: PagingMode::Pages,
+            display_mode: DisplayMode::Relaxed,
+            content_view: ContentView::Text,
+            content_focus: false,
+            tree: TreeState::new(),
+            grid_view: false,
+            grid: GridState::new(),
+            stream_offset: 0,
+            stream_has_before: false,
+            stream_has_after: false,
+            stream_by_offset: false,
+            stream_from_end: false,
+            stream_first_id: None,
+            stream_last_id: None,
             filter: None,
+            sort: None,
+            projection: None,
             loading: false,
+            loading_message: String::new(),
+            write_message: None,
+            spinner_frame: 0,
             error: None,
+            notice: None,
             should_quit: false,
             selected_db_index: 0,
             selected_coll_index: 0,
@@ -61,19 +215,84 @@ impl AppState {
             selected_doc_index: 0,
             doc_scroll_offset: 0,
             connection_input: String::from("mongodb://localhost:27017"),
-            input_mode: false,
+            connection_form: None,
             filter_input: String::new(),
             filter_mode: false,
-            query_input: String::new(),
+            query_input: TextEditor::new(),
+            sort_input: TextEditor::new(),
+            projection_input: TextEditor::new(),
             query_mode: false,
+            query_target: QueryTarget::Filter,
             connection_history: Vec::new(),
             selected_history_index: 0,
-            show_history: false,
+            connection_focus: ConnectionFocus::Input,
+            profiles: Vec::new(),
+            selected_profile_index: 0,
+            pending_database: None,
+            config: AppConfig::default(),
+            aggregation: AggregationState::new(),
+            update: UpdateState::new(),
+            journal: Journal::new(),
+            modal: None,
+            pending_edit: None,
+        }
+    }
+
+    pub fn set_config(&mut self, config: AppConfig) {
+        self.page_size = config.default_page_size;
+        self.config = config;
+    }
+
+    pub fn set_connection(
+        &mut self,
+        server_info: ServerInfo,
+        read_only: bool,
+        profile: Option<ConnectionProfile>,
+    ) {
+        self.pending_database = profile
+            .as_ref()
+            .and_then(|profile| profile.default_database.clone());
+        let tunnel = profile
+            .as_ref()
+            .and_then(|profile| profile.ssh_tunnel.as_ref())
+            .map(|tunnel| TunnelStatus {
+                label: tunnel.label(),
+                open: true,
+            });
+        self.connection = Some(ConnectionState {
+            server_info,
+            read_only,
+            profile,
+            tunnel,
+        });
+    }
+
+    /// Forgets the connection and everything loaded through it, and goes
+    /// back to the connection screen.
+    pub fn disconnect(&mut self) {
+        self.connection = None;
+        self.current_database = None;
+        self.current_collection = None;
+        self.pending_database = None;
+        self.databases.clear();
+        self.collections.clear();
+        self.documents.clear();
+        self.journal.clear();
+        self.set_screen(Screen::Connection);
+    }
+
+    pub fn set_tunnel_closed(&mut self) {
+        if let Some(tunnel) = self
+            .connection
+            .as_mut()
+            .and_then(|conn| conn.tunnel.as_mut())
+        {
+            tunnel.open = false;
         }
     }
 
-    pub fn set_connection(&mut self, uri: String, server_info: ServerInfo) {
-        self.connection = Some(ConnectionState { uri, server_info });
+    pub fn is_read_only(&self) -> bool {
+        self.connection.as_ref().is_some_and(|conn| conn.read_only)
     }
 
     pub fn set_databases(&mut self, databases: Vec<DatabaseInfo>) {
@@ -88,22 +307,219 @@ impl AppState {
 
     pub fn set_documents(&mut self, documents: Vec<Document>) {
         self.documents = documents;
+        self.selected_doc_index = 0;
+        self.doc_scroll_offset = 0;
+        self.tree.reset_cursor();
+        self.grid.update_columns(&self.documents);
+    }
+
+    /// Stores one page of query results and the filtered total they belong to.
+    pub fn set_query_result(&mut self, page: usize, result: QueryResult) {
+        self.current_page = page;
+        self.total_count = result.total_count;
+        self.query_time = Some(result.execution_time);
+        self.set_documents(result.document);
+    }
+
+    pub fn toggle_paging_mode(&mut self) {
+        self.paging_mode = match self.paging_mode {
+            PagingMode::Pages => PagingMode::Stream,
+            PagingMode::Stream => PagingMode::Pages,
+        };
+    }
+
+    pub fn toggle_content_view(&mut self) {
+        self.content_view = match self.content_view {
+            ContentView::Text => ContentView::Tree,
+            ContentView::Tree => ContentView::Text,
+        };
+    }
+
+    pub fn toggle_grid_view(&mut self) {
+        self.grid_view = !self.grid_view;
+    }
+
+    pub fn toggle_content_focus(&mut self) {
+        self.content_focus = !self.content_focus;
+    }
+
+    pub fn cycle_display_mode(&mut self) {
+        self.display_mode = self.display_mode.next();
+        self.doc_scroll_offset = 0;
+    }
+
+    /// Merges a streamed batch into the document window, dropping documents
+    /// from the opposite end once the window exceeds `stream_window_size`.
+    pub fn apply_stream_batch(&mut self, fetch: StreamFetch, batch: StreamBatch) {
+        let StreamBatch {
+            documents: batch,
+            total_count,
+            far_end_id,
+        } = batch;
+        if let Some(total_count) = total_count {
+            self.total_count = total_count;
+        }
+        let full_batch = batch.len() >= self.page_size;
+        let window = self.config.stream_window_size.max(self.page_size);
+        let id = |document: Option<&Document>| document.and_then(|d| d.get("_id")).cloned();
+
+        match fetch {
+            StreamFetch::First => {
+                self.stream_first_id = id(batch.first());
+                self.stream_last_id = far_end_id;
+                self.set_documents(batch);
+                self.stream_by_offset = false;
+                self.stream_from_end = false;
+                self.stream_offset = 0;
+                self.stream_has_before = false;
+                self.stream_has_after = full_batch;
+            }
+            StreamFetch::Last => {
+                self.stream_first_id = far_end_id;
+                self.stream_last_id = id(batch.last());
+                self.stream_by_offset = false;
+                self.stream_from_end = true;
+                self.stream_offset = (self.total_count as usize).saturating_sub(batch.len());
+                self.stream_has_before = self.stream_offset > 0;
+                self.stream_has_after = false;
+                self.set_documents(batch);
+                self.selected_doc_index = self.documents.len().saturating_sub(1);
+            }
+            StreamFetch::Next => {
+                self.stream_has_after = full_batch;
+                self.documents.extend(batch);
+                if self.stream_by_offset && self.stream_from_end {
+                    self.stream_has_after = self.stream_after() > 0;
+                } else if !full_batch
+                    && !self.stream_by_offset
+                    && id(self.documents.last()) != self.stream_last_id
+                {
+                    // $gt stopped at the last _id of its type short of the
+                    // last _id overall
+                    self.fall_back_to_offset();
+                    self.stream_has_after = true;
+                }
+                let excess = self.documents.len().saturating_sub(window);
+                if excess > 0 {
+                    self.documents.drain(..excess);
+                    self.stream_offset += excess;
+                    self.selected_doc_index = self.selected_doc_index.saturating_sub(excess);
+                    self.stream_has_before = true;
+                }
+            }
+            StreamFetch::Previous => {
+                let added = batch.len();
+                self.stream_offset = self.stream_offset.saturating_sub(added);
+                self.selected_doc_index += added;
+                self.documents.splice(..0, batch);
+                self.stream_has_before = if self.stream_by_offset && !self.stream_from_end {
+                    self.stream_offset > 0
+                } else if !full_batch
+                    && !self.stream_by_offset
+                    && id(self.documents.first()) != self.stream_first_id
+                {
+                    self.fall_back_to_offset();
+                    true
+                } else {
+                    full_batch
+                };
+                if self.documents.len() > window {
+                    self.documents.truncate(window);
+                    self.stream_has_after = true;
+                }
+            }
+        }
+        self.grid.update_columns(&self.documents);
+    }
+
+    fn fall_back_to_offset(&mut self) {
+        if !self.stream_by_offset {
+            self.stream_by_offset = true;
+            self.set_notice(Some(
+                "_id values have mixed types; streaming on with skip, which is slower".to_string(),
+            ));
+        }
+    }
+
+    /// Number of documents after the streamed window. Exact for a stream
+    /// started at the last document, where `stream_offset` was derived from
+    /// the same total.
+    pub fn stream_after(&self) -> usize {
+        (self.total_count as usize).saturating_sub(self.stream_offset + self.documents.len())
+    }
+
+    /// Batch to fetch when the selection is within a few documents of either
+    /// end of the streamed window.
+    pub fn pending_stream_fetch(&self) -> Option<StreamFetch> {
+        if self.paging_mode != PagingMode::Stream || self.documents.is_empty() {
+            return None;
+        }
+        if self.stream_has_after
+            && self.selected_doc_index + STREAM_PREFETCH >= self.documents.len()
+        {
+            Some(StreamFetch::Next)
+        } else if self.stream_has_before && self.selected_doc_index < STREAM_PREFETCH {
+            Some(StreamFetch::Previous)
+        } else {
+            None
+        }
+    }
+
+    pub fn page_count(&self) -> usize {
+        (self.total_count as usize).div_ceil(self.page_size).max(1)
+    }
+
+    pub fn last_page(&self) -> usize {
+        self.page_count() - 1
+    }
+
+    /// Switches to the next larger preset page size, keeping the first
+    /// visible document on the new current page.
+    pub fn increase_page_size(&mut self) {
+        if let Some(size) = PAGE_SIZES.iter().find(|size| **size > self.page_size) {
+            self.resize_page(*size);
+        }
+    }
+
+    pub fn decrease_page_size(&mut self) {
+        if let Some(size) = PAGE_SIZES.iter().rev().find(|size| **size < self.page_size) {
+            self.resize_page(*size);
+        }
+    }
+
+    fn resize_page(&mut self, page_size: usize) {
+        let first_doc = self.current_page * self.page_size;
+        self.page_size = page_size;
+        self.current_page = first_doc / page_size;
     }
 
     pub fn set_loading(&mut self, loading: bool) {
         self.loading = loading;
     }
 
+    /// Marks a background task as running; the footer shows a spinner with
+    /// `message` until `set_loading(false)` is called.
+    pub fn start_loading(&mut self, message: &str) {
+        self.loading = true;
+        self.loading_message = message.to_string();
+        self.error = None;
+        self.notice = None;
+    }
+
+    pub fn tick(&mut self) {
+        self.spinner_frame = self.spinner_frame.wrapping_add(1);
+    }
+
     pub fn set_error(&mut self, error: Option<String>) {
         self.error = error;
     }
 
-    pub fn quit(&mut self) {
-        self.should_quit = true;
+    pub fn set_notice(&mut self, notice: Option<String>) {
+        self.notice = notice;
     }
 
-    pub fn is_connected(&self) -> bool {
-        self.connection.is_some()
+    pub fn quit(&mut self) {
+        self.should_quit = true;
     }
 
     pub fn select_next_db(&mut self) {
@@ -151,15 +567,24 @@ impl AppState {
     }
 
     pub fn select_next_doc(&mut self) {
-        if !self.documents.is_empty() {
+        self.tree.reset_cursor();
+        if self.paging_mode == PagingMode::Stream {
+            // the window is extended instead of wrapping around
+            if self.selected_doc_index + 1 < self.documents.len() {
+                self.selected_doc_index += 1;
+            }
+        } else if !self.documents.is_empty() {
             self.selected_doc_index = (self.selected_doc_index + 1) % self.documents.len();
         }
     }
 
     pub fn select_prev_doc(&mut self) {
-        if !self.documents.is_empty() {
+        self.tree.reset_cursor();
+        if self.paging_mode == PagingMode::Stream {
+            self.selected_doc_index = self.selected_doc_index.saturating_sub(1);
+        } else if !self.documents.is_empty() {
             if self.selected_doc_index == 0 {
-                self.selected_doc_index == self.documents.len() - 1;
+                self.selected_doc_index = self.documents.len() - 1;
             } else {
                 self.selected_doc_index -= 1;
             }
@@ -180,14 +605,6 @@ impl AppState {
         self.documents.get(self.selected_doc_index)
     }
 
-    pub fn enter_input_mode(&mut self) {
-        self.input_mode = true;
-    }
-
-    pub fn exit_input_mode(&mut self) {
-        self.input_mode = false;
-    }
-
     pub fn clear_input(&mut self) {
         self.connection_input.clear();
     }
@@ -213,6 +630,41 @@ impl AppState {
         self.filter = None;
     }
 
+    /// Forgets filter, sort and projection, e.g. when opening another
+    /// collection.
+    pub fn reset_query(&mut self) {
+        self.clear_filter();
+        self.query_input.clear();
+        self.sort = None;
+        self.sort_input.clear();
+        self.projection = None;
+        self.projection_input.clear();
+    }
+
+    /// Query for `collection` in the current database using the active
+    /// filter, sort and projection.
+    pub fn query_for(&self, collection: &str) -> Option<QueryParams> {
+        let database = self.current_database.as_ref()?;
+        let mut query = QueryParams::new(database, collection);
+        query.filter = self.filter.clone();
+        query.sort = self.sort.clone();
+        query.projection = self.projection.clone();
+        query.limit = self.page_size as i64;
+        Some(query)
+    }
+
+    pub fn current_query(&self) -> Option<QueryParams> {
+        self.query_for(self.current_collection.as_ref()?)
+    }
+
+    /// Makes `query` the active query once its documents have loaded.
+    pub fn commit_query(&mut self, query: QueryParams) {
+        self.current_collection = Some(query.collection);
+        self.filter = query.filter;
+        self.sort = query.sort;
+        self.projection = query.projection;
+    }
+
     pub fn push_filter_char(&mut self, c: char) {
         self.filter_input.push(c);
     }
@@ -221,64 +673,90 @@ impl AppState {
         self.filter_input.pop();
     }
 
-    pub fn apply_filter(&mut self) -> Result<(), String> {
-        let input = if !self.query_input.is_empty() {
-            &self.query_input
-        } else {
-            &self.filter_input
-        };
-
+    /// Parses the input of the part being edited in advanced query mode. The
+    /// result only becomes active once documents for it have been loaded.
+    pub fn parse_query_input(&self) -> Result<Option<Document>, String> {
+        let input = self.query_editor().text();
+        let input = input.trim();
         if input.is_empty() {
-            self.filter = None;
-            return Ok(());
+            return Ok(None);
         }
 
-        match serde_json::from_str::<serde_json::Value>(input) {
-            Ok(json_value) => match mongodb::bson::to_document(&json_value) {
-                Ok(doc) => {
-                    self.filter = Some(doc);
-                    Ok(())
-                }
-                Err(e) => Err(format!("Invalid filter: {}", e)),
-            },
-            Err(e) => Err(format!("Invalid JSON: {}", e)),
+        syntax::parse_document(input).map(Some).map_err(|e| {
+            format!(
+                "Invalid {}: {}",
+                self.query_target.label().to_lowercase(),
+                e
+            )
+        })
+    }
+
+    pub fn enter_page_input_mode(&mut self) {
+        self.page_input_mode = true;
+        self.page_input.clear();
+    }
+
+    pub fn exit_page_input_mode(&mut self) {
+        self.page_input_mode = false;
+    }
+
+    pub fn push_page_char(&mut self, c: char) {
+        if c.is_ascii_digit() {
+            self.page_input.push(c);
         }
     }
 
-    pub fn enter_query_mode(&mut self) {
-        self.query_mode = true;
+    pub fn pop_page_char(&mut self) {
+        self.page_input.pop();
     }
 
-    pub fn exit_query_mode(&mut self) {
-        self.query_mode = false;
+    /// Zero-based page index typed in page input mode, clamped to the last page.
+    pub fn parse_page_input(&self) -> Option<usize> {
+        let page: usize = self.page_input.parse().ok()?;
+        Some(page.saturating_sub(1).min(self.last_page()))
     }
 
-    pub fn push_every_char(&mut self, c: char) {
-        self.query_input.push(c);
+    pub fn enter_query_mode(&mut self, target: QueryTarget) {
+        self.query_mode = true;
+        self.query_target = target;
     }
 
-    pub fn pop_every_char(&mut self) {
-        self.query_input.pop();
+    pub fn exit_query_mode(&mut self) {
+        self.query_mode = false;
     }
 
     pub fn clear_query(&mut self) {
-        self.query_input.clear();
+        self.query_editor_mut().clear();
     }
 
-    pub fn push_query_char(&mut self, c: char) {
-        self.query_input.push(c);
+    /// Editor of the part being edited in advanced query mode.
+    pub fn query_editor(&self) -> &TextEditor {
+        match self.query_target {
+            QueryTarget::Filter => &self.query_input,
+            QueryTarget::Sort => &self.sort_input,
+            QueryTarget::Projection => &self.projection_input,
+        }
     }
 
-    pub fn pop_query_char(&mut self) {
-        self.query_input.pop();
+    pub fn query_editor_mut(&mut self) -> &mut TextEditor {
+        match self.query_target {
+            QueryTarget::Filter => &mut self.query_input,
+            QueryTarget::Sort => &mut self.sort_input,
+            QueryTarget::Projection => &mut self.projection_input,
+        }
     }
 
     pub fn set_connection_history(&mut self, history: Vec<String>) {
         self.connection_history = history;
     }
 
-    pub fn toggle_history(&mut self) {
-        self.show_history = !self.show_history;
+    /// Moves the focus from the input to the profiles, the history and back.
+    pub fn next_connection_focus(&mut self) {
+        self.connection_focus = match self.connection_focus {
+            ConnectionFocus::Input => ConnectionFocus::Profiles,
+            ConnectionFocus::Profiles => ConnectionFocus::History,
+            ConnectionFocus::History => ConnectionFocus::Input,
+        };
     }
 
     pub fn select_next_history(&mut self) {
@@ -302,6 +780,33 @@ impl AppState {
             .get(self.selected_history_index)
             .cloned()
     }
+
+    pub fn set_profiles(&mut self, profiles: Vec<ConnectionProfile>) {
+        self.profiles = profiles;
+        if self.selected_profile_index >= self.profiles.len() {
+            self.selected_profile_index = self.profiles.len().saturating_sub(1);
+        }
+    }
+
+    pub fn select_next_profile(&mut self) {
+        if !self.profiles.is_empty() {
+            self.selected_profile_index = (self.selected_profile_index + 1) % self.profiles.len();
+        }
+    }
+
+    pub fn select_prev_profile(&mut self) {
+        if !self.profiles.is_empty() {
+            if self.selected_profile_index == 0 {
+                self.selected_profile_index = self.profiles.len() - 1;
+            } else {
+                self.selected_profile_index -= 1;
+            }
+        }
+    }
+
+    pub fn get_selected_profile(&self) -> Option<&ConnectionProfile> {
+        self.profiles.get(self.selected_profile_index)
+    }
 }
 
 impl Default for AppState {
@@ -309,3 +814,55 @@ impl Default for AppState {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    fn batch(ids: &[Bson], total_count: Option<u64>, far_end_id: Option<Bson>) -> StreamBatch {
+        StreamBatch {
+            documents: ids.iter().map(|id| doc! { "_id": id.clone() }).collect(),
+            total_count,
+            far_end_id,
+        }
+    }
+
+    fn streaming() -> AppState {
+        let mut state = AppState::new();
+        state.paging_mode = PagingMode::Stream;
+        state.page_size = 2;
+        state
+    }
+
+    #[test]
+    fn short_keyset_batch_before_the_last_id_switches_to_skip() {
+        let mut state = streaming();
+        let last = Bson::String("z".to_string());
+        // the total is an estimate and claims nothing is left
+        state.apply_stream_batch(
+            StreamFetch::First,
+            batch(&[1.into(), 2.into()], Some(2), Some(last.clone())),
+        );
+        state.apply_stream_batch(StreamFetch::Next, batch(&[3.into()], None, None));
+        assert!(state.stream_by_offset);
+        assert!(state.stream_has_after);
+
+        state.apply_stream_batch(StreamFetch::Next, batch(&[last], None, None));
+        assert!(!state.stream_has_after);
+    }
+
+    #[test]
+    fn short_batch_ending_at_the_last_id_ends_the_stream() {
+        let mut state = streaming();
+        // the total is an estimate and claims more are left
+        state.apply_stream_batch(
+            StreamFetch::First,
+            batch(&[1.into(), 2.into()], Some(10), Some(3.into())),
+        );
+        state.apply_stream_batch(StreamFetch::Next, batch(&[3.into()], None, None));
+        assert!(!state.stream_by_offset);
+        assert!(!state.stream_has_after);
+    }
+}
@@ -0,0 +1,280 @@
+use std::{future::Future, pin::Pin};
+
+use mongodb::{
+    Client,
+    bson::{Bson, Document},
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use super::{state::StreamFetch, update::UpdateSample};
+use crate::{
+    config::ConnectionProfile,
+    credentials::CredentialStore,
+    error::AppError,
+    models::{CollectionInfo, DatabaseInfo, QueryParams, QueryResult, ServerInfo, StreamBatch},
+    services::{query::QueryService, tunnel::Tunnel},
+};
+
+/// What a document is read again for before it is opened in the editor.
+#[derive(Debug, Clone, Copy)]
+pub enum FetchPurpose {
+    Edit,
+    Clone,
+}
+
+/// Outcome of a background database operation, sent back to the event loop.
+// one is sent per task, so the size of the largest variant does not matter
+#[allow(clippy::large_enum_variant)]
+pub enum TaskResult {
+    Connected {
+        uri: String,
+        profile: Option<ConnectionProfile>,
+        // password to store under this key once the connection works
+        remember: Option<(String, String)>,
+        result: Result<(Client, ServerInfo, Option<Tunnel>), AppError>,
+    },
+    /// The URI built by the connection form was checked before connecting.
+    UriValidated {
+        uri: String,
+        result: Result<(), AppError>,
+    },
+    /// The credential file was opened, to go on connecting to `uri`.
+    CredentialsUnlocked {
+        uri: String,
+        profile: Option<ConnectionProfile>,
+        result: Result<CredentialStore, AppError>,
+    },
+    DatabasesLoaded(Result<Vec<DatabaseInfo>, AppError>),
+    CollectionsLoaded {
+        database: String,
+        result: Result<Vec<CollectionInfo>, AppError>,
+    },
+    /// Collections of the database chosen as the target of a copy.
+    CloneCollectionsLoaded {
+        database: String,
+        result: Result<Vec<CollectionInfo>, AppError>,
+    },
+    DocumentsLoaded {
+        query: QueryParams,
+        page: usize,
+        result: Result<QueryResult, AppError>,
+    },
+    AggregationPreview {
+        through: Option<usize>,
+        result: Result<Vec<Document>, AppError>,
+    },
+    DocumentInserted {
+        database: String,
+        collection: String,
+        result: Result<Bson, AppError>,
+    },
+    DocumentFetched {
+        database: String,
+        collection: String,
+        purpose: FetchPurpose,
+        result: Result<Option<Document>, AppError>,
+    },
+    DocumentReplaced {
+        database: String,
+        collection: String,
+        original: Document,
+        result: Result<(), AppError>,
+    },
+    DeleteCounted {
+        database: String,
+        collection: String,
+        filter: Document,
+        bulk: bool,
+        result: Result<u64, AppError>,
+    },
+    // the deleted documents come back when there were few enough to keep
+    DocumentsDeleted {
+        database: String,
+        collection: String,
+        // documents before the delete, kept even if it failed
+        previous: Option<Vec<Document>>,
+        result: Result<u64, AppError>,
+    },
+    UpdateDryRun {
+        // filter and update text the dry run was made for
+        input: (String, String),
+        result: Result<(u64, Vec<UpdateSample>), AppError>,
+    },
+    DocumentsUpdated {
+        database: String,
+        collection: String,
+        // documents before the update, kept even if it failed
+        previous: Option<Vec<Document>>,
+        result: Result<(u64, u64), AppError>,
+    },
+    Undone(Result<u64, AppError>),
+    StreamBatchLoaded {
+        query: QueryParams,
+        fetch: StreamFetch,
+        result: Result<StreamBatch, AppError>,
+    },
+}
+
+struct RunningTask {
+    id: u64,
+    handle: JoinHandle<()>,
+    // service and comment used to kill the server-side operation on abort
+    operation: Option<(QueryService, String)>,
+}
+
+type BoxedTask = Pin<Box<dyn Future<Output = TaskResult> + Send>>;
+
+// a read waiting for the running write, not started yet
+struct QueuedTask {
+    operation: Option<(QueryService, String)>,
+    future: BoxedTask,
+}
+
+/// Runs database calls on the tokio runtime so the render loop never blocks.
+///
+/// Only one foreground read is tracked at a time: spawning a new one aborts
+/// the previous read, and results from superseded reads are dropped.
+///
+/// Writes run in a slot of their own that is never aborted, as the server
+/// may already have applied part of them. While one runs, new reads wait
+/// (only the latest is kept) and start once the write has finished.
+pub struct TaskManager {
+    sender: mpsc::UnboundedSender<(u64, TaskResult)>,
+    receiver: mpsc::UnboundedReceiver<(u64, TaskResult)>,
+    current: Option<RunningTask>,
+    write: Option<u64>,
+    queued: Option<QueuedTask>,
+    next_id: u64,
+}
+
+impl TaskManager {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            sender,
+            receiver,
+            current: None,
+            write: None,
+            queued: None,
+            next_id: 0,
+        }
+    }
+
+    pub fn spawn<F>(&mut self, future: F)
+    where
+        F: Future<Output = TaskResult> + Send + 'static,
+    {
+        self.start_or_queue(None, Box::pin(future));
+    }
+
+    /// Spawns a query whose server-side operations can be killed if the task
+    /// is aborted. `build` receives a copy of `query_service` tagged with a
+    /// unique comment and returns the future to run.
+    pub fn spawn_query<F, B>(&mut self, query_service: QueryService, build: B)
+    where
+        B: FnOnce(QueryService) -> F,
+        F: Future<Output = TaskResult> + Send + 'static,
+    {
+        let tag = format!("mongonaut-{}-{}", std::process::id(), self.next_id);
+        self.next_id += 1;
+        let future = build(query_service.clone().with_comment(tag.clone()));
+        self.start_or_queue(Some((query_service, tag)), Box::pin(future));
+    }
+
+    /// Spawns a write that runs to completion whatever else is started or
+    /// cancelled meanwhile. Returns false, without running it, while
+    /// another write is in flight. A running read is aborted, as its result
+    /// would predate the write.
+    pub fn spawn_write<F>(&mut self, future: F) -> bool
+    where
+        F: Future<Output = TaskResult> + Send + 'static,
+    {
+        if self.write.is_some() {
+            return false;
+        }
+        self.abort();
+        self.write = Some(self.run(future).0);
+        true
+    }
+
+    fn start_or_queue(&mut self, operation: Option<(QueryService, String)>, future: BoxedTask) {
+        self.abort();
+        if self.write.is_some() {
+            self.queued = Some(QueuedTask { operation, future });
+            return;
+        }
+        let (id, handle) = self.run(future);
+        self.current = Some(RunningTask {
+            id,
+            handle,
+            operation,
+        });
+    }
+
+    fn run<F>(&mut self, future: F) -> (u64, JoinHandle<()>)
+    where
+        F: Future<Output = TaskResult> + Send + 'static,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let sender = self.sender.clone();
+        let handle = tokio::spawn(async move {
+            let result = future.await;
+            let _ = sender.send((id, result));
+        });
+        (id, handle)
+    }
+
+    /// Returns the next finished result, skipping results of aborted tasks.
+    /// A finished write starts the read queued behind it.
+    pub fn try_recv(&mut self) -> Option<TaskResult> {
+        while let Ok((id, result)) = self.receiver.try_recv() {
+            if self.write == Some(id) {
+                self.write = None;
+                if let Some(QueuedTask { operation, future }) = self.queued.take() {
+                    self.start_or_queue(operation, future);
+                }
+                return Some(result);
+            }
+            if self.current.as_ref().is_some_and(|task| task.id == id) {
+                self.current = None;
+                return Some(result);
+            }
+        }
+        None
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.current.is_some() || self.is_writing()
+    }
+
+    pub fn is_writing(&self) -> bool {
+        self.write.is_some()
+    }
+
+    /// Aborts the running or queued read; a write keeps running. Dropping
+    /// the task also drops its open cursors, which makes the driver kill
+    /// them on the server; operations still executing are killed by their
+    /// comment in the background.
+    pub fn abort(&mut self) -> bool {
+        let queued = self.queued.take().is_some();
+        let Some(task) = self.current.take() else {
+            return queued;
+        };
+        task.handle.abort();
+
+        if let Some((query_service, tag)) = task.operation {
+            tokio::spawn(async move {
+                let _ = query_service.kill_operations(&tag).await;
+            });
+        }
+        true
+    }
+}
+
+impl Default for TaskManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashSet;
+
+use mongodb::bson::{Bson, Document};
+
+/// One visible line of a document shown as a tree.
+#[derive(Debug)]
+pub struct TreeRow<'a> {
+    /// Dotted path from the document root, e.g. `items.0.name`.
+    pub path: String,
+    pub depth: usize,
+    pub key: String,
+    pub value: &'a Bson,
+    pub expanded: bool,
+}
+
+impl TreeRow<'_> {
+    pub fn is_container(&self) -> bool {
+        matches!(self.value, Bson::Document(_) | Bson::Array(_))
+    }
+}
+
+/// Expanded nodes and cursor of the tree view. Expanded paths are kept when
+/// moving to another document, so similar documents open the same way.
+#[derive(Debug, Default)]
+pub struct TreeState {
+    pub expanded: HashSet<String>,
+    pub cursor: usize,
+}
+
+impl TreeState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rows of `doc` with collapsed subtrees left out.
+    pub fn rows<'a>(&self, doc: &'a Document) -> Vec<TreeRow<'a>> {
+        let mut rows = Vec::new();
+        self.push_rows(&mut rows, fields(doc), "", 0);
+        rows
+    }
+
+    fn push_rows<'a>(
+        &self,
+        rows: &mut Vec<TreeRow<'a>>,
+        fields: Vec<(String, &'a Bson)>,
+        parent: &str,
+        depth: usize,
+    ) {
+        for (key, value) in fields {
+            let path = if parent.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", parent, key)
+            };
+            let expanded = self.expanded.contains(&path);
+
+            rows.push(TreeRow {
+                path: path.clone(),
+                depth,
+                key,
+                value,
+                expanded,
+            });
+
+            if expanded {
+                self.push_rows(rows, children(value), &path, depth + 1);
+            }
+        }
+    }
+
+    pub fn reset_cursor(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_down(&mut self, rows: usize, by: usize) {
+        self.cursor = (self.cursor + by).min(rows.saturating_sub(1));
+    }
+
+    pub fn move_up(&mut self, by: usize) {
+        self.cursor = self.cursor.saturating_sub(by);
+    }
+
+    /// Expands the node under the cursor, or collapses it if it is open.
+    pub fn toggle(&mut self, doc: &Document) {
+        let rows = self.rows(doc);
+        let Some(row) = rows.get(self.cursor).filter(|row| row.is_container()) else {
+            return;
+        };
+        let path = row.path.clone();
+        if !self.expanded.remove(&path) {
+            self.expanded.insert(path);
+        }
+    }
+
+    pub fn expand(&mut self, doc: &Document) {
+        let rows = self.rows(doc);
+        if let Some(row) = rows.get(self.cursor).filter(|row| row.is_container()) {
+            let path = row.path.clone();
+            self.expanded.insert(path);
+        }
+    }
+
+    /// Collapses the node under the cursor, or moves to its parent when it
+    /// is a leaf or already collapsed.
+    pub fn collapse(&mut self, doc: &Document) {
+        let rows = self.rows(doc);
+        let Some(row) = rows.get(self.cursor) else {
+            return;
+        };
+
+        if row.expanded {
+            let path = row.path.clone();
+            self.expanded.remove(&path);
+        } else if let Some((parent, _)) = row.path.rsplit_once('.')
+            && let Some(index) = rows.iter().position(|row| row.path == parent)
+        {
+            self.cursor = index;
+        }
+    }
+
+    pub fn expand_all(&mut self, doc: &Document) {
+        collect_containers(&mut self.expanded, fields(doc), "");
+    }
+
+    pub fn collapse_all(&mut self) {
+        self.expanded.clear();
+        self.cursor = 0;
+    }
+}
+
+fn collect_containers(paths: &mut HashSet<String>, fields: Vec<(String, &Bson)>, parent: &str) {
+    for (key, value) in fields {
+        if !matches!(value, Bson::Document(_) | Bson::Array(_)) {
+            continue;
+        }
+        let path = if parent.is_empty() {
+            key
+        } else {
+            format!("{}.{}", parent, key)
+        };
+        collect_containers(paths, children(value), &path);
+        paths.insert(path);
+    }
+}
+
+fn fields(doc: &Document) -> Vec<(String, &Bson)> {
+    doc.iter()
+        .map(|(key, value)| (key.clone(), value))
+        .collect()
+}
+
+// fields of a document or elements of an array, keyed by index
+fn children(value: &Bson) -> Vec<(String, &Bson)> {
+    match value {
+        Bson::Document(doc) => fields(doc),
+        Bson::Array(items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (i.to_string(), item))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
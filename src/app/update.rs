@@ -0,0 +1,330 @@
+use crossterm::event::KeyEvent;
+use mongodb::{
+    bson::{Bson, Document, doc},
+    options::UpdateModifications,
+};
+
+use super::{diff::FieldChange, editor::TextEditor};
+use crate::{
+    services::query::WRITE_STAGES,
+    syntax::{self, DisplayMode},
+};
+
+/// Documents shown before and after the update in a dry run.
+pub const SAMPLE_SIZE: i64 = 5;
+
+// stages the server accepts in a pipeline update
+const UPDATE_STAGES: [&str; 6] = [
+    "$addFields",
+    "$set",
+    "$project",
+    "$unset",
+    "$replaceRoot",
+    "$replaceWith",
+];
+
+/// Editor on the update screen receiving key presses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpdateFocus {
+    Filter,
+    Update,
+}
+
+/// One sampled document and what the update would change in it.
+#[derive(Debug, Clone)]
+pub struct UpdateSample {
+    pub id: Bson,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug)]
+pub struct UpdateState {
+    pub filter: TextEditor,
+    pub update: TextEditor,
+    pub focus: UpdateFocus,
+    // documents matching the filter in the last dry run
+    pub matched: Option<u64>,
+    pub samples: Vec<UpdateSample>,
+    // why the dry run could not show samples, e.g. an unsupported operator
+    pub sample_note: Option<String>,
+    pub preview_scroll: usize,
+    // filter and update text the dry run was made for; the update only runs
+    // while both are unchanged
+    pub dry_run_input: Option<(String, String)>,
+}
+
+impl UpdateState {
+    pub fn new() -> Self {
+        Self {
+            filter: TextEditor::new(),
+            update: TextEditor::new(),
+            focus: UpdateFocus::Update,
+            matched: None,
+            samples: Vec::new(),
+            sample_note: None,
+            preview_scroll: 0,
+            dry_run_input: None,
+        }
+    }
+
+    /// Starts from the active `filter`, keeping an update already written.
+    pub fn open(&mut self, filter: Option<&Document>) {
+        let filter = Bson::Document(filter.cloned().unwrap_or_default());
+        self.filter
+            .set_text(&syntax::to_compact_string(&filter, DisplayMode::Relaxed));
+        if self.update.is_empty() {
+            self.update.set_text("{\"$set\": {}}");
+        }
+        self.focus = UpdateFocus::Update;
+        self.clear_dry_run();
+    }
+
+    /// Passes `key` to the focused editor, returning false if it was not an
+    /// editing key.
+    pub fn handle_editor_key(&mut self, key: KeyEvent) -> bool {
+        match self.focus {
+            UpdateFocus::Filter => self.filter.handle_key(key),
+            UpdateFocus::Update => self.update.handle_key(key),
+        }
+    }
+
+    pub fn toggle_focus(&mut self) {
+        self.focus = match self.focus {
+            UpdateFocus::Filter => UpdateFocus::Update,
+            UpdateFocus::Update => UpdateFocus::Filter,
+        };
+    }
+
+    pub fn input(&self) -> (String, String) {
+        (self.filter.text(), self.update.text())
+    }
+
+    /// Parses both editors into a filter and an update.
+    pub fn parse(&self) -> Result<(Document, UpdateModifications), String> {
+        let filter = self.filter.text();
+        let filter = if filter.trim().is_empty() {
+            Document::new()
+        } else {
+            syntax::parse_document(&filter).map_err(|e| format!("Invalid filter: {}", e))?
+        };
+        Ok((filter, parse_update(&self.update.text())?))
+    }
+
+    /// True if the last dry run was made for what the editors hold now.
+    pub fn is_dry_run_current(&self) -> bool {
+        self.dry_run_input.as_ref() == Some(&self.input())
+    }
+
+    pub fn set_dry_run(
+        &mut self,
+        input: (String, String),
+        matched: u64,
+        samples: Vec<UpdateSample>,
+    ) {
+        self.dry_run_input = Some(input);
+        self.matched = Some(matched);
+        self.samples = samples;
+        self.preview_scroll = 0;
+    }
+
+    pub fn clear_dry_run(&mut self) {
+        self.dry_run_input = None;
+        self.matched = None;
+        self.samples.clear();
+        self.sample_note = None;
+        self.preview_scroll = 0;
+    }
+
+    pub fn scroll_preview_down(&mut self) {
+        self.preview_scroll += 1;
+    }
+
+    pub fn scroll_preview_up(&mut self) {
+        self.preview_scroll = self.preview_scroll.saturating_sub(1);
+    }
+}
+
+impl Default for UpdateState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses a document of update operators such as `{$set: {...}}`, or an
+/// array of aggregation stages for a pipeline update.
+pub fn parse_update(input: &str) -> Result<UpdateModifications, String> {
+    let value = syntax::parse_value(input).map_err(|e| format!("Invalid update: {}", e))?;
+    match value {
+        Bson::Document(update) => {
+            if update.is_empty() {
+                return Err("The update is empty".to_string());
+            }
+            if let Some(key) = update.keys().find(|key| !key.starts_with('$')) {
+                return Err(format!(
+                    "'{}' is not an update operator; use e.g. {{$set: {{{}: ...}}}}",
+                    key, key
+                ));
+            }
+            Ok(UpdateModifications::Document(update))
+        }
+        Bson::Array(stages) => stages
+            .into_iter()
+            .enumerate()
+            .map(|(i, stage)| match stage {
+                Bson::Document(stage) if stage.len() == 1 => {
+                    let operator = stage.keys().next().cloned().unwrap_or_default();
+                    if UPDATE_STAGES.contains(&operator.as_str()) {
+                        Ok(stage)
+                    } else {
+                        Err(format!(
+                            "Stage {} uses {}; update pipelines only take {}",
+                            i + 1,
+                            operator,
+                            UPDATE_STAGES.join(", ")
+                        ))
+                    }
+                }
+                _ => Err(format!(
+                    "Stage {} must be a document with exactly one operator",
+                    i + 1
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(UpdateModifications::Pipeline),
+        _ => Err("An update must be a document of operators or an array of stages".to_string()),
+    }
+}
+
+/// The update as a value, for display.
+pub fn update_to_bson(update: &UpdateModifications) -> Bson {
+    match update {
+        UpdateModifications::Document(update) => Bson::Document(update.clone()),
+        UpdateModifications::Pipeline(stages) => {
+            Bson::Array(stages.iter().cloned().map(Bson::Document).collect())
+        }
+        _ => Bson::Null,
+    }
+}
+
+/// Aggregation stages that compute what `update` makes of a document, used
+/// to preview it without writing. Pipeline updates are used as they are;
+/// operator updates are translated, which works for the common operators
+/// only.
+pub fn preview_stages(update: &UpdateModifications) -> Result<Vec<Document>, String> {
+    let UpdateModifications::Document(update) = update else {
+        return match update {
+            // the preview runs as an aggregation, where these would write
+            UpdateModifications::Pipeline(stages) => match stages
+                .iter()
+                .find_map(|stage| WRITE_STAGES.iter().find(|op| stage.contains_key(**op)))
+            {
+                Some(operator) => Err(format!("{} stages cannot be previewed", operator)),
+                None => Ok(stages.clone()),
+            },
+            _ => Err("This kind of update cannot be previewed".to_string()),
+        };
+    };
+
+    let mut stages = Vec::new();
+    for (operator, fields) in update {
+        let Bson::Document(fields) = fields else {
+            return Err(format!("The value of {} must be a document", operator));
+        };
+        stages.extend(operator_stages(operator, fields)?);
+    }
+    Ok(stages)
+}
+
+fn operator_stages(operator: &str, fields: &Document) -> Result<Vec<Document>, String> {
+    // positional paths such as `arr.$[].x` are not field paths in an
+    // aggregation, so only the match count can be shown for them
+    if let Some(field) = fields.keys().find(|field| is_positional(field)) {
+        return Err(format!(
+            "The dry run cannot show changes to positional path '{}'; the match count is still accurate",
+            field
+        ));
+    }
+    // `$field` refers to the current value of `field` in an expression
+    let current = |field: &str| Bson::String(format!("${}", field));
+    let set_each = |value: &dyn Fn(&str, &Bson) -> Bson| {
+        let set: Document = fields
+            .iter()
+            .map(|(field, arg)| (field.clone(), value(field, arg)))
+            .collect();
+        vec![doc! { "$set": set }]
+    };
+
+    let stages = match operator {
+        "$set" => set_each(&|_, arg| doc! { "$literal": arg.clone() }.into()),
+        "$unset" => {
+            let fields: Vec<Bson> = fields.keys().cloned().map(Bson::String).collect();
+            vec![doc! { "$unset": fields }]
+        }
+        "$inc" => set_each(&|field, arg| {
+            doc! { "$add": [{ "$ifNull": [current(field), 0] }, arg.clone()] }.into()
+        }),
+        "$mul" => set_each(&|field, arg| {
+            doc! { "$multiply": [{ "$ifNull": [current(field), 0] }, arg.clone()] }.into()
+        }),
+        "$min" => set_each(&|field, arg| doc! { "$min": [current(field), arg.clone()] }.into()),
+        "$max" => set_each(&|field, arg| doc! { "$max": [current(field), arg.clone()] }.into()),
+        "$currentDate" => set_each(&|_, _| Bson::String("$$NOW".to_string())),
+        "$rename" => {
+            let mut stages = Vec::new();
+            for (field, target) in fields {
+                let Bson::String(target) = target else {
+                    return Err(format!("The new name of '{}' must be a string", field));
+                };
+                stages.push(doc! { "$set": { target: current(field) } });
+                stages.push(doc! { "$unset": [field] });
+            }
+            stages
+        }
+        // only applies when upserting, which this screen never does
+        "$setOnInsert" => Vec::new(),
+        _ => {
+            return Err(format!(
+                "The dry run cannot show {} changes; the match count is still accurate",
+                operator
+            ));
+        }
+    };
+    Ok(stages)
+}
+
+// true for paths with a `$`, `$[]` or `$[identifier]` segment
+fn is_positional(path: &str) -> bool {
+    path.split('.').any(|segment| segment.starts_with('$'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipeline_stages() {
+        assert!(parse_update(r#"[{$set: {a: 1}}, {$unset: "b"}]"#).is_ok());
+        let e = parse_update(r#"[{$set: {a: 1}}, {$merge: {into: "other"}}]"#).unwrap_err();
+        assert!(e.starts_with("Stage 2 uses $merge"), "{}", e);
+        assert!(parse_update(r#"[{$out: "other"}]"#).is_err());
+    }
+
+    #[test]
+    fn preview_refuses_write_stages() {
+        let update = UpdateModifications::Pipeline(vec![
+            doc! { "$set": { "a": 1 } },
+            doc! { "$merge": { "into": "other" } },
+        ]);
+        assert!(preview_stages(&update).is_err());
+    }
+
+    #[test]
+    fn preview_refuses_positional_paths() {
+        for path in ["arr.$.x", "arr.$[].x", "arr.$[elem].x"] {
+            let update = UpdateModifications::Document(doc! { "$set": { path: 1 } });
+            assert!(preview_stages(&update).is_err(), "{}", path);
+        }
+        let update = UpdateModifications::Document(doc! { "$set": { "price": 1 } });
+        assert!(preview_stages(&update).is_ok());
+    }
+}
@@ -1,7 +1,12 @@
 use std::{fs, path::PathBuf};
 
+use ratatui::style::Color;
 use serde::{Deserialize, Serialize};
 
+use crate::{credentials, models::AppConfig};
+
+/// URIs connected to recently, stored in `~/.mongonaut/history.json`
+/// without their passwords.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectionHistory {
     pub uris: Vec<String>,
@@ -13,6 +18,7 @@ impl ConnectionHistory {
     }
 
     pub fn add_uri(&mut self, uri: String) {
+        let uri = credentials::strip_password(&uri);
         if !self.uris.contains(&uri) {
             self.uris.insert(0, uri);
             if self.uris.len() > 10 {
@@ -25,12 +31,24 @@ impl ConnectionHistory {
     }
 
     pub fn load() -> Self {
-        if let Some(path) = Self::config_path() {
-            if let Ok(contents) = fs::read_to_string(&path) {
-                if let Ok(history) = serde_json::from_str(&contents) {
-                    return history;
+        if let Some(path) = Self::config_path()
+            && let Ok(contents) = fs::read_to_string(&path)
+            && let Ok(mut history) = serde_json::from_str::<Self>(&contents)
+        {
+            // files written by older versions kept passwords
+            let stripped: Vec<String> = history
+                .uris
+                .iter()
+                .map(|uri| credentials::strip_password(uri))
+                .collect();
+            if stripped != history.uris {
+                history.uris = Vec::new();
+                for uri in stripped.into_iter().rev() {
+                    history.add_uri(uri);
                 }
+                let _ = history.save();
             }
+            return history;
         }
         Self::new()
     }
@@ -50,3 +68,374 @@ impl ConnectionHistory {
         Ok(())
     }
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedPipeline {
+    pub name: String,
+    pub pipeline: String,
+}
+
+/// Aggregation pipelines saved from the aggregation screen, stored in
+/// `~/.mongonaut/pipelines.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SavedPipelines {
+    pub pipelines: Vec<SavedPipeline>,
+}
+
+impl SavedPipelines {
+    /// Saves `pipeline` under `name`, replacing a pipeline with the same name.
+    pub fn upsert(&mut self, name: String, pipeline: String) {
+        self.pipelines.retain(|saved| saved.name != name);
+        self.pipelines.insert(0, SavedPipeline { name, pipeline });
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.pipelines.len() {
+            self.pipelines.remove(index);
+        }
+    }
+
+    /// Reads the saved pipelines. A missing file holds none; one that does
+    /// not parse is an error, so it is never overwritten by a save.
+    pub fn load() -> Result<Self, String> {
+        let Some(path) = Self::config_path() else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| format!("Invalid {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    fn config_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".mongonaut").join("pipelines.json"))
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = Self::config_path() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let json = serde_json::to_string_pretty(self)?;
+            fs::write(path, json)?;
+        }
+        Ok(())
+    }
+}
+
+// read preference modes accepted in a profile
+const READ_PREFERENCES: [&str; 5] = [
+    "primary",
+    "primaryPreferred",
+    "secondary",
+    "secondaryPreferred",
+    "nearest",
+];
+
+// rustls has no switch for the host name check alone
+pub const ALLOW_INVALID_HOSTNAMES_UNSUPPORTED: &str = "Allowing invalid hostnames needs a build with the openssl-tls feature; \
+     allow invalid certificates instead, which skips both checks";
+
+/// A named connection with the options to open it with. Unset options keep
+/// the values from the URI or the driver defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConnectionProfile {
+    pub name: String,
+    pub uri: String,
+    /// Database opened right after connecting.
+    pub default_database: Option<String>,
+    /// Color of the profile name in the header, by name or "#rrggbb".
+    pub color: Option<String>,
+    pub read_preference: Option<String>,
+    pub connect_timeout_seconds: Option<u64>,
+    pub server_selection_timeout_seconds: Option<u64>,
+    pub tls: Option<bool>,
+    pub tls_ca_file: Option<PathBuf>,
+    /// PEM file with the client certificate followed by its private key.
+    pub tls_cert_key_file: Option<PathBuf>,
+    pub tls_allow_invalid_certificates: Option<bool>,
+    /// Only with the `openssl-tls` feature; rustls always checks the host
+    /// name unless invalid certificates are allowed.
+    pub tls_allow_invalid_hostnames: Option<bool>,
+    pub read_only: bool,
+    // last, as TOML writes tables after plain keys
+    pub ssh_tunnel: Option<SshTunnel>,
+}
+
+/// SSH server the connection is forwarded through, for databases only
+/// reachable from a bastion host.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SshTunnel {
+    pub host: String,
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    /// Private key; without one ssh uses the agent and its default keys.
+    pub key_file: Option<PathBuf>,
+}
+
+impl SshTunnel {
+    /// `user@host:port` as shown in the header.
+    pub fn label(&self) -> String {
+        let mut label = match &self.user {
+            Some(user) => format!("{}@{}", user, self.host),
+            None => self.host.clone(),
+        };
+        if let Some(port) = self.port {
+            label.push_str(&format!(":{}", port));
+        }
+        label
+    }
+}
+
+impl ConnectionProfile {
+    pub fn new(uri: String) -> Self {
+        Self {
+            uri,
+            ..Self::default()
+        }
+    }
+
+    /// Checks the fields that would otherwise only fail when connecting.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("A profile needs a name".to_string());
+        }
+        if !self.uri.starts_with("mongodb://") && !self.uri.starts_with("mongodb+srv://") {
+            return Err("The URI must start with mongodb:// or mongodb+srv://".to_string());
+        }
+        if let Some(color) = &self.color
+            && color.parse::<Color>().is_err()
+        {
+            return Err(format!("Unknown color '{}'", color));
+        }
+        if let Some(mode) = &self.read_preference
+            && !READ_PREFERENCES.contains(&mode.as_str())
+        {
+            return Err(format!(
+                "Unknown read preference '{}'; use one of {}",
+                mode,
+                READ_PREFERENCES.join(", ")
+            ));
+        }
+        if self.tls == Some(false) && self.has_tls_options() {
+            return Err("TLS options are set but tls = false".to_string());
+        }
+        if self.tls_allow_invalid_hostnames == Some(true) && !cfg!(feature = "openssl-tls") {
+            return Err(ALLOW_INVALID_HOSTNAMES_UNSUPPORTED.to_string());
+        }
+        if let Some(tunnel) = &self.ssh_tunnel {
+            if tunnel.host.trim().is_empty() {
+                return Err("ssh_tunnel needs a host".to_string());
+            }
+            // ssh would read them as options
+            if tunnel.host.starts_with('-')
+                || tunnel
+                    .user
+                    .as_deref()
+                    .is_some_and(|user| user.starts_with('-'))
+            {
+                return Err("ssh_tunnel host and user cannot start with '-'".to_string());
+            }
+            // the forward goes to one address, and servers found through
+            // DNS or the replica set would bypass it
+            let hosts = self.uri.split(['/', '?']).nth(2).unwrap_or_default();
+            if self.uri.starts_with("mongodb+srv://") || hosts.contains(',') {
+                return Err("An SSH tunnel needs a mongodb:// URI with a single host".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether any TLS setting besides `tls` itself is given, which turns
+    /// TLS on unless it is switched off.
+    pub fn has_tls_options(&self) -> bool {
+        self.tls_ca_file.is_some()
+            || self.tls_cert_key_file.is_some()
+            || self.tls_allow_invalid_certificates.is_some()
+            || self.tls_allow_invalid_hostnames.is_some()
+    }
+
+    pub fn color(&self) -> Option<Color> {
+        self.color.as_deref().and_then(|color| color.parse().ok())
+    }
+}
+
+/// Connection profiles, stored in `~/.mongonaut/profiles.toml` as
+/// `[[profiles]]` tables. Unlike the history they are only changed on
+/// request.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConnectionProfiles {
+    #[serde(default)]
+    pub profiles: Vec<ConnectionProfile>,
+}
+
+impl ConnectionProfiles {
+    /// Saves `profile`, in place of the profile named `original` if given.
+    /// Fails if another profile already has its name.
+    pub fn upsert(
+        &mut self,
+        original: Option<&str>,
+        profile: ConnectionProfile,
+    ) -> Result<(), String> {
+        if self
+            .profiles
+            .iter()
+            .any(|p| p.name == profile.name && Some(p.name.as_str()) != original)
+        {
+            return Err(format!("A profile named '{}' already exists", profile.name));
+        }
+
+        match original.and_then(|name| self.profiles.iter().position(|p| p.name == name)) {
+            Some(index) => self.profiles[index] = profile,
+            None => self.profiles.push(profile),
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.profiles.retain(|profile| profile.name != name);
+    }
+
+    /// Reads the profiles file. A missing file holds no profiles; one that
+    /// does not parse is an error, so it is never overwritten by a save.
+    pub fn load() -> Result<Self, String> {
+        let Some(path) = Self::config_path() else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                toml::from_str(&contents).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    fn config_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".mongonaut").join("profiles.toml"))
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = Self::config_path() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, toml::to_string_pretty(self)?)?;
+        }
+        Ok(())
+    }
+}
+
+// settings read from ~/.mongonaut/config.toml; missing keys keep their defaults
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ConfigFile {
+    default_page_size: Option<usize>,
+    stream_window_size: Option<usize>,
+    cache_ttl_seconds: Option<u64>,
+    connection_timeout_seconds: Option<u64>,
+    read_only: Option<bool>,
+    production_hosts: Vec<String>,
+    theme: ThemeFile,
+}
+
+// `[theme]` colors by name (e.g. "lightblue") or hex ("#ff8800")
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ThemeFile {
+    key: Option<String>,
+    string: Option<String>,
+    number: Option<String>,
+    boolean: Option<String>,
+    null: Option<String>,
+    bson_type: Option<String>,
+}
+
+impl AppConfig {
+    /// Reads config.toml over the defaults. A missing file keeps the
+    /// defaults; one that does not parse is an error, since dropping it
+    /// would also drop `read_only` and `production_hosts`.
+    pub fn load() -> Result<Self, String> {
+        let mut app_config = Self::default();
+
+        let file = match Self::config_path() {
+            Some(path) => config::Config::builder()
+                .add_source(config::File::from(path.clone()).required(false))
+                .build()
+                .and_then(|settings| settings.try_deserialize::<ConfigFile>())
+                .map_err(|e| format!("Invalid {}: {}", path.display(), e))?,
+            None => ConfigFile::default(),
+        };
+
+        if let Some(page_size) = file.default_page_size.filter(|size| *size > 0) {
+            app_config.default_page_size = page_size;
+        }
+        if let Some(window) = file.stream_window_size.filter(|size| *size > 0) {
+            app_config.stream_window_size = window;
+        }
+        if let Some(ttl) = file.cache_ttl_seconds {
+            app_config.cache_ttl_seconds = ttl;
+        }
+        if let Some(timeout) = file.connection_timeout_seconds {
+            app_config.connection_timeout_seconds = timeout;
+        }
+        if let Some(read_only) = file.read_only {
+            app_config.read_only = read_only;
+        }
+        app_config.production_hosts = file.production_hosts;
+
+        let theme = &mut app_config.theme;
+        let colors = [
+            (&file.theme.key, &mut theme.key_color),
+            (&file.theme.string, &mut theme.string_color),
+            (&file.theme.number, &mut theme.number_color),
+            (&file.theme.boolean, &mut theme.boolean_color),
+            (&file.theme.null, &mut theme.null_color),
+            (&file.theme.bson_type, &mut theme.bson_type_color),
+        ];
+        for (name, color) in colors {
+            if let Some(parsed) = name.as_deref().and_then(|name| name.parse().ok()) {
+                *color = parsed;
+            }
+        }
+        Ok(app_config)
+    }
+
+    /// Whether connections to `host` must be read-only, either everywhere
+    /// or because it matches one of the production host patterns.
+    pub fn is_read_only_host(&self, host: &str) -> bool {
+        self.read_only
+            || self
+                .production_hosts
+                .iter()
+                .any(|pattern| matches_pattern(&pattern.to_lowercase(), &host.to_lowercase()))
+    }
+
+    fn config_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".mongonaut").join("config.toml"))
+    }
+}
+
+// glob match where `*` stands for any run of characters
+fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let Some((first, rest)) = pattern.split_once('*') else {
+        return pattern == text;
+    };
+    let Some(mut remaining) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let mut parts: Vec<&str> = rest.split('*').collect();
+    let last = parts.pop().unwrap_or_default();
+    for part in parts {
+        match remaining.find(part) {
+            Some(pos) => remaining = &remaining[pos + part.len()..],
+            None => return false,
+        }
+    }
+    remaining.len() >= last.len() && remaining.ends_with(last)
+}
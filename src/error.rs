@@ -1,7 +1,7 @@
 use thiserror::Error;
 
-#[derive(Debug,Error)]
-pub enum AppError{
+#[derive(Debug, Error)]
+pub enum AppError {
     #[error("Connection error: {0}")]
     Connection(String),
 
@@ -19,6 +19,10 @@ pub enum AppError{
 
     #[error("Invalid input: {0}")]
     InvalidInput(String),
-}
 
-pub type Result<T> = std::result::Result<T,AppError>;
+    #[error("Read-only mode: {0}")]
+    ReadOnly(String),
+
+    #[error("Credentials: {0}")]
+    Credentials(String),
+}
@@ -1,5 +1,4 @@
-use crossterm::event::KeyCode;
-use mongodb::bson::Document;
+use mongodb::bson::{Bson, Document};
 use ratatui::style::Color;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
@@ -26,13 +25,8 @@ pub struct ServerInfo {
     pub version: String,
     pub host: String,
     pub port: u16,
-}
-
-#[derive(Debug, Clone)]
-pub struct IndexInfo {
-    pub name: String,
-    pub keys: Document,
-    pub unique: bool,
+    /// Every host the client was given, after SRV records are resolved.
+    pub hosts: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -43,6 +37,48 @@ pub struct QueryParams {
     pub skip: u64,
     pub limit: i64,
     pub sort: Option<Document>,
+    pub projection: Option<Document>,
+}
+
+impl QueryParams {
+    pub fn new(database: &str, collection: &str) -> Self {
+        Self {
+            database: database.to_string(),
+            collection: collection.to_string(),
+            filter: None,
+            skip: 0,
+            limit: 0,
+            sort: None,
+            projection: None,
+        }
+    }
+}
+
+/// Position in `_id` order to read a batch of documents from, used to stream
+/// through large collections without `skip`.
+#[derive(Debug, Clone)]
+pub enum Keyset {
+    First,
+    Last,
+    After(Bson),
+    Before(Bson),
+    /// `skip` documents into `_id` order. `$gt`/`$lt` only match `_id`s of
+    /// the same BSON type, so this is used once a stream crosses a type
+    /// boundary.
+    Offset(u64),
+    /// Like `Offset`, counted back from the end of `_id` order.
+    OffsetFromEnd(u64),
+}
+
+/// A batch of streamed documents.
+#[derive(Debug, Clone)]
+pub struct StreamBatch {
+    pub documents: Vec<Document>,
+    /// Filtered total, read when the stream (re)starts at either end.
+    pub total_count: Option<u64>,
+    /// `_id` at the other end of `_id` order, read with the total; a short
+    /// batch stopping before it ran into a different `_id` type.
+    pub far_end_id: Option<Bson>,
 }
 
 #[derive(Debug, Clone)]
@@ -55,68 +91,53 @@ pub struct QueryResult {
 #[derive(Debug, Clone)]
 pub struct AppConfig {
     pub default_page_size: usize,
+    pub stream_window_size: usize,
     pub cache_ttl_seconds: u64,
     pub connection_timeout_seconds: u64,
     pub theme: Theme,
-    pub keybindings: KeyBindings,
+    // refuse writes on every connection (`--read-only` or `read_only = true`)
+    pub read_only: bool,
+    // host patterns such as "*.prod.example.com" that connect read-only
+    pub production_hosts: Vec<String>,
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
             default_page_size: 20,
+            stream_window_size: 500,
             cache_ttl_seconds: 300,
             connection_timeout_seconds: 5,
             theme: Theme::default(),
-            keybindings: KeyBindings::default(),
+            read_only: false,
+            production_hosts: Vec::new(),
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Theme {
-    pub primary_color: Color,
     pub secondary_color: Color,
-    pub highlight_color: Color,
-    pub error_color: Color,
+    // document syntax highlighting
+    pub key_color: Color,
+    pub string_color: Color,
+    pub number_color: Color,
+    pub boolean_color: Color,
+    pub null_color: Color,
+    /// ObjectId, dates, binary and other BSON-specific values.
+    pub bson_type_color: Color,
 }
 
 impl Default for Theme {
     fn default() -> Self {
         Self {
-            primary_color: Color::Cyan,
             secondary_color: Color::Gray,
-            highlight_color: Color::Yellow,
-            error_color: Color::Red,
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct KeyBindings {
-    pub quit: Vec<KeyCode>,
-    pub navigate_up: Vec<KeyCode>,
-    pub navigate_down: Vec<KeyCode>,
-    pub select: Vec<KeyCode>,
-    pub back: Vec<KeyCode>,
-    pub refresh: Vec<KeyCode>,
-    pub filter: Vec<KeyCode>,
-    pub export: Vec<KeyCode>,
-    pub help: Vec<KeyCode>,
-}
-
-impl Default for KeyBindings {
-    fn default() -> Self {
-        Self {
-            quit: vec![KeyCode::Char('q'), KeyCode::Esc],
-            navigate_up: vec![KeyCode::Up, KeyCode::Char('k')],
-            navigate_down: vec![KeyCode::Down, KeyCode::Char('j')],
-            select: vec![KeyCode::Enter],
-            back: vec![KeyCode::Backspace, KeyCode::Char('h')],
-            refresh: vec![KeyCode::Char('r')],
-            filter: vec![KeyCode::Char('/')],
-            export: vec![KeyCode::Char('e')],
-            help: vec![KeyCode::Char('?')],
+            key_color: Color::Cyan,
+            string_color: Color::Green,
+            number_color: Color::LightMagenta,
+            boolean_color: Color::Yellow,
+            null_color: Color::DarkGray,
+            bson_type_color: Color::LightBlue,
         }
     }
 }
@@ -0,0 +1,222 @@
+use std::{
+    fs::OpenOptions,
+    future::Future,
+    io::Write,
+    path::PathBuf,
+    sync::{
+        OnceLock,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Sender},
+    },
+    thread,
+};
+
+use mongodb::bson::{Bson, DateTime, Document};
+use serde_json::{Map, Value};
+
+use crate::error::AppError;
+
+pub fn namespace(db: &str, collection: &str) -> String {
+    format!("{}.{}", db, collection)
+}
+
+/// What an operation returned or changed, as recorded in the log.
+pub enum Affected {
+    Documents(u64),
+    Updated {
+        matched: u64,
+        modified: u64,
+    },
+    /// Databases or collections listed, which are not documents.
+    Listed(u64),
+    Inserted(Bson),
+    Nothing,
+}
+
+/// One command sent to the server, before its outcome is known.
+pub struct AuditEntry {
+    namespace: String,
+    operation: &'static str,
+    filter: Option<Document>,
+    details: Vec<(&'static str, Bson)>,
+}
+
+impl AuditEntry {
+    pub fn new(namespace: impl Into<String>, operation: &'static str) -> Self {
+        Self {
+            namespace: namespace.into(),
+            operation,
+            filter: None,
+            details: Vec::new(),
+        }
+    }
+
+    pub fn filter(mut self, filter: Option<&Document>) -> Self {
+        self.filter = filter.cloned();
+        self
+    }
+
+    /// Records `value` under `key`, e.g. the pipeline of an aggregation.
+    pub fn detail(mut self, key: &'static str, value: Bson) -> Self {
+        self.details.push((key, value));
+        self
+    }
+}
+
+// lines waiting for the writer thread, or a request to confirm that all
+// lines sent before it are written
+enum Message {
+    Line(String),
+    Flush(Sender<()>),
+}
+
+/// Appends every command sent to the server to `~/.mongonaut/audit.log`,
+/// one JSON object per line: an `attempted` line before the command is
+/// sent and a `completed` or `failed` line with the same `id` once it
+/// returns. A command whose task was dropped meanwhile keeps only the
+/// first. Lines are written by a thread of their own, so the file is never
+/// touched on the async path.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    host: String,
+}
+
+impl AuditLog {
+    pub fn new(host: impl Into<String>) -> Self {
+        Self { host: host.into() }
+    }
+
+    /// Logs `entry` as attempted, runs `operation` and logs its outcome;
+    /// `affected` tells what a successful result means for the counts.
+    pub async fn track<T, F>(
+        &self,
+        entry: AuditEntry,
+        affected: impl FnOnce(&T) -> Affected,
+        operation: F,
+    ) -> Result<T, AppError>
+    where
+        F: Future<Output = Result<T, AppError>>,
+    {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let id = format!(
+            "{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        );
+
+        let mut attempt = self.line(&id, "attempted", &entry);
+        if let Some(filter) = &entry.filter {
+            let filter = Bson::Document(filter.clone()).into_relaxed_extjson();
+            attempt.insert("filter".into(), filter);
+        }
+        for (key, value) in &entry.details {
+            attempt.insert((*key).into(), value.clone().into_relaxed_extjson());
+        }
+        Self::send(Message::Line(Value::Object(attempt).to_string()));
+
+        let result = operation.await;
+        let status = if result.is_ok() {
+            "completed"
+        } else {
+            "failed"
+        };
+        let mut outcome = self.line(&id, status, &entry);
+        match result.as_ref().map(affected) {
+            Ok(Affected::Documents(count)) => {
+                outcome.insert("count".into(), count.into());
+            }
+            Ok(Affected::Updated { matched, modified }) => {
+                outcome.insert("matched".into(), matched.into());
+                outcome.insert("modified".into(), modified.into());
+            }
+            Ok(Affected::Listed(count)) => {
+                outcome.insert("listed".into(), count.into());
+            }
+            Ok(Affected::Inserted(id)) => {
+                outcome.insert("insertedId".into(), id.into_relaxed_extjson());
+            }
+            Ok(Affected::Nothing) => {}
+            Err(e) => {
+                outcome.insert("error".into(), Value::String(e.to_string()));
+            }
+        }
+        Self::send(Message::Line(Value::Object(outcome).to_string()));
+        result
+    }
+
+    /// Waits until every line logged so far is in the file; called before
+    /// the program exits.
+    pub fn flush() {
+        let (done, wait) = mpsc::channel();
+        Self::send(Message::Flush(done));
+        let _ = wait.recv();
+    }
+
+    // fields both lines of an operation share
+    fn line(&self, id: &str, status: &str, entry: &AuditEntry) -> Map<String, Value> {
+        let mut line = Map::new();
+        let timestamp = DateTime::now().try_to_rfc3339_string().unwrap_or_default();
+        line.insert("timestamp".into(), Value::String(timestamp));
+        line.insert("id".into(), Value::String(id.to_string()));
+        line.insert("status".into(), Value::String(status.to_string()));
+        line.insert("host".into(), Value::String(self.host.clone()));
+        line.insert("namespace".into(), Value::String(entry.namespace.clone()));
+        line.insert("operation".into(), Value::String(entry.operation.into()));
+        line
+    }
+
+    fn send(message: Message) {
+        static WRITER: OnceLock<Sender<Message>> = OnceLock::new();
+        let writer = WRITER.get_or_init(|| {
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                for message in receiver {
+                    match message {
+                        // a failing log must not turn a completed write
+                        // into an error
+                        Message::Line(line) => {
+                            let _ = Self::append(&line);
+                        }
+                        Message::Flush(done) => {
+                            let _ = done.send(());
+                        }
+                    }
+                }
+            });
+            sender
+        });
+        // if the thread is gone, a flush request is dropped with the
+        // message and its waiter returns at once
+        let _ = writer.send(message);
+    }
+
+    fn append(line: &str) -> std::io::Result<()> {
+        let Some(path) = Self::log_path() else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // the log holds filters and whole documents, so only the user may
+        // read it; a log created before this was enforced is tightened too
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut permissions = file.metadata()?.permissions();
+            if permissions.mode() & 0o077 != 0 {
+                permissions.set_mode(0o600);
+                file.set_permissions(permissions)?;
+            }
+        }
+        writeln!(file, "{}", line)
+    }
+
+    fn log_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".mongonaut").join("audit.log"))
+    }
+}
@@ -1,12 +1,29 @@
 use std::time::Duration;
 
-use mongodb::{Client, options::ClientOptions};
+use mongodb::{
+    Client,
+    error::{Error, ErrorKind},
+    options::{ClientOptions, ReadPreference, SelectionCriteria, ServerAddress, Tls, TlsOptions},
+};
 
-use crate::{error::AppError, models::ServerInfo};
+use crate::{
+    config::{ALLOW_INVALID_HOSTNAMES_UNSUPPORTED, ConnectionProfile, SshTunnel},
+    credentials,
+    error::AppError,
+    models::ServerInfo,
+    services::{audit::AuditLog, crud::CrudService, query::QueryService, tunnel::Tunnel},
+};
+
+// ssh may have to reach a bastion and authenticate before forwarding
+const TUNNEL_TIMEOUT: Duration = Duration::from_secs(15);
 
 pub struct ConnectionService {
     client: Option<Client>,
     server_info: Option<ServerInfo>,
+    // services handed out refuse to write while set
+    read_only: bool,
+    // forward the client connects through, closed with the connection
+    tunnel: Option<Tunnel>,
 }
 
 impl ConnectionService {
@@ -14,18 +31,46 @@ impl ConnectionService {
         Self {
             client: None,
             server_info: None,
+            read_only: false,
+            tunnel: None,
         }
     }
 
-    pub async fn connect(&mut self, uri: &str) -> Result<ServerInfo,AppError> {
+    /// Opens a client for `uri`, with the options of `profile` if given, and
+    /// verifies it with a ping. The caller attaches the result once the
+    /// (possibly backgrounded) call completes. If the profile has an SSH
+    /// tunnel, it is opened first and returned for the caller to keep.
+    pub async fn connect(
+        uri: &str,
+        profile: Option<&ConnectionProfile>,
+    ) -> Result<(Client, ServerInfo, Option<Tunnel>), AppError> {
         // parsing connection string
         let mut client_options = ClientOptions::parse(uri)
             .await
-            .map_err(|e| AppError::Connection(format!("Invalid URI: {}", e)))?;
+            .map_err(|e| Self::parse_error(uri, e))?;
 
         // setting timeout
         client_options.connect_timeout = Some(Duration::from_secs(5));
         client_options.server_selection_timeout = Some(Duration::from_secs(5));
+        if let Some(profile) = profile {
+            // profiles.toml may have been edited by hand
+            profile.validate().map_err(AppError::Connection)?;
+            Self::apply_profile(&mut client_options, profile);
+        }
+        Self::check_tls_files(&client_options)?;
+        // before a tunnel replaces them with its local end
+        let hosts = client_options
+            .hosts
+            .iter()
+            .map(|address| match address {
+                ServerAddress::Tcp { host, .. } => host.clone(),
+                other => other.to_string(),
+            })
+            .collect();
+        let tunnel = match profile.and_then(|profile| profile.ssh_tunnel.as_ref()) {
+            Some(config) => Some(Self::open_tunnel(&mut client_options, config).await?),
+            None => None,
+        };
 
         let client = Client::with_options(client_options)
             .map_err(|e| AppError::Connection(format!("Failed to create client: {}", e)))?;
@@ -34,7 +79,7 @@ impl ConnectionService {
             .database("admin")
             .run_command(mongodb::bson::doc! { "ping": 1 })
             .await
-            .map_err(|e| AppError::Connection(format!("Connection failed: {}", e)))?;
+            .map_err(|e| AppError::Connection(Self::describe_connect_error(&e)))?;
 
         let build_info = client
             .database("admin")
@@ -42,66 +87,265 @@ impl ConnectionService {
             .await
             .map_err(|e| AppError::Connection(format!("Failed to get server info: {}", e)))?;
         let version = build_info
-        .get_str("version").unwrap_or("unknown").to_string();
+            .get_str("version")
+            .unwrap_or("unknown")
+            .to_string();
 
         let (host, port) = Self::parse_host_port(uri);
         let server_info = ServerInfo {
             version,
             host,
             port,
+            hosts,
         };
 
-        self.client = Some(client);
-        self.server_info = Some(server_info.clone());
+        Ok((client, server_info, tunnel))
+    }
 
-        Ok(server_info)
+    // points the client at a local forward to the URI's only host
+    async fn open_tunnel(
+        options: &mut ClientOptions,
+        config: &SshTunnel,
+    ) -> Result<Tunnel, AppError> {
+        let [ServerAddress::Tcp { host, port }] = options.hosts.as_slice() else {
+            return Err(AppError::Connection(
+                "An SSH tunnel needs a mongodb:// URI with a single host".to_string(),
+            ));
+        };
+        let tunnel = Tunnel::open(config, host, port.unwrap_or(27017), TUNNEL_TIMEOUT).await?;
+        options.hosts = vec![ServerAddress::Tcp {
+            host: "127.0.0.1".to_string(),
+            port: Some(tunnel.local_port()),
+        }];
+        // replica set members would be reached by their own names, around
+        // the tunnel
+        options.direct_connection = Some(true);
+        Ok(tunnel)
     }
-    pub async fn disconnect(&mut self) -> Result<(),AppError> {
-        self.client = None;
-        self.server_info = None;
+
+    /// Checks `uri` the way the driver reads it, without connecting. A
+    /// password still to come from the credential file or the prompt is
+    /// stood in for, as some mechanisms insist on one.
+    pub async fn validate(uri: &str) -> Result<(), AppError> {
+        let uri = match credentials::credential_key(uri) {
+            Some(_) if credentials::password(uri).is_none() => {
+                credentials::with_password(uri, "placeholder")
+            }
+            _ => uri.to_string(),
+        };
+        let options = ClientOptions::parse(&uri)
+            .await
+            .map_err(|e| Self::parse_error(&uri, e))?;
+        Self::check_tls_files(&options)
+    }
+
+    fn parse_error(uri: &str, error: Error) -> AppError {
+        // the driver only knows the option with OpenSSL and calls it invalid
+        if !cfg!(feature = "openssl-tls") && uri.to_lowercase().contains("tlsallowinvalidhostnames")
+        {
+            return AppError::Connection(ALLOW_INVALID_HOSTNAMES_UNSUPPORTED.to_string());
+        }
+        AppError::Connection(format!("Invalid URI: {}", error))
+    }
+
+    // the driver only reads the files once it connects, and then reports a
+    // missing one as a failure to reach the server
+    fn check_tls_files(options: &ClientOptions) -> Result<(), AppError> {
+        let Some(Tls::Enabled(tls)) = &options.tls else {
+            return Ok(());
+        };
+        let files = [
+            ("CA file", &tls.ca_file_path),
+            ("client certificate file", &tls.cert_key_file_path),
+        ];
+        for (what, path) in files {
+            if let Some(path) = path
+                && !path.is_file()
+            {
+                return Err(AppError::Connection(format!(
+                    "TLS {} not found: {}",
+                    what,
+                    path.display()
+                )));
+            }
+        }
         Ok(())
     }
 
-    pub fn is_connected(&self) -> bool {
-        self.client.is_some()
+    // says whether the server was unreachable, refused the credentials or
+    // failed the TLS checks; the driver's text is kept for the details
+    fn describe_connect_error(error: &Error) -> String {
+        let message = error.to_string();
+        let lower = message.to_lowercase();
+        let has = |patterns: &[&str]| patterns.iter().any(|pattern| lower.contains(pattern));
+
+        let reason = match *error.kind {
+            ErrorKind::Authentication { .. } => "Authentication failed",
+            ErrorKind::InvalidTlsConfig { .. } => "Invalid TLS settings",
+            // rustls and OpenSSL name the same failures differently
+            _ if has(&["not valid for name", "notvalidforname", "hostname mismatch"]) => {
+                "The server certificate does not match the host name \
+                 (connect with a name the certificate lists)"
+            }
+            _ if has(&[
+                "received fatal alert",
+                "alert bad certificate",
+                "alert unknown ca",
+            ]) =>
+            {
+                "The server rejected the TLS handshake \
+                 (check the client certificate and whether the server trusts its CA)"
+            }
+            _ if has(&[
+                "invalid peer certificate",
+                "certificate verify failed",
+                "self-signed certificate",
+                "self signed certificate",
+            ]) =>
+            {
+                "Certificate verification failed \
+                 (set the CA file that signed the server certificate, \
+                 or allow invalid certificates for testing)"
+            }
+            _ if has(&[
+                "corrupt message",
+                "invalidcontenttype",
+                "wrong version number",
+                "close_notify",
+                "handshake",
+            ]) =>
+            {
+                "TLS handshake failed (check that TLS is on for both the server and the connection)"
+            }
+            _ => "Network error, the server could not be reached",
+        };
+        // a selection timeout wraps the last error from each server in a
+        // topology dump; the error alone is enough
+        let detail = message
+            .rsplit_once("Error: ")
+            .and_then(|(_, error)| error.split_once(", labels"))
+            .map(|(error, _)| error.trim_start_matches("Kind: "))
+            .unwrap_or(&message);
+        format!("{}: {}", reason, detail)
     }
 
-    pub async fn test_connection(&self) -> Result<bool,AppError> {
-        if let Some(client) = &self.client {
-            client
-                .database("admin")
-                .run_command(mongodb::bson::doc! { "ping": 1 })
-                .await
-                .map_err(|e| AppError::Connection(format!("Connection test failed: {}", e)))?;
-            Ok(true)
-        } else {
-            Ok(false)
+    // profile settings win over the URI's, which win over the defaults
+    fn apply_profile(options: &mut ClientOptions, profile: &ConnectionProfile) {
+        if let Some(seconds) = profile.connect_timeout_seconds {
+            options.connect_timeout = Some(Duration::from_secs(seconds));
+        }
+        if let Some(seconds) = profile.server_selection_timeout_seconds {
+            options.server_selection_timeout = Some(Duration::from_secs(seconds));
+        }
+        if let Some(mode) = &profile.read_preference {
+            let read_preference = match mode.as_str() {
+                "primaryPreferred" => ReadPreference::PrimaryPreferred { options: None },
+                "secondary" => ReadPreference::Secondary { options: None },
+                "secondaryPreferred" => ReadPreference::SecondaryPreferred { options: None },
+                "nearest" => ReadPreference::Nearest { options: None },
+                _ => ReadPreference::Primary,
+            };
+            options.selection_criteria = Some(SelectionCriteria::ReadPreference(read_preference));
+        }
+
+        match profile.tls {
+            Some(false) => options.tls = Some(Tls::Disabled),
+            Some(true) => options.tls = Some(Tls::Enabled(Self::tls_options(options, profile))),
+            None if profile.has_tls_options() => {
+                options.tls = Some(Tls::Enabled(Self::tls_options(options, profile)));
+            }
+            None => {}
+        }
+    }
+
+    // the profile's TLS settings over those from the URI
+    fn tls_options(options: &ClientOptions, profile: &ConnectionProfile) -> TlsOptions {
+        let mut tls = match &options.tls {
+            Some(Tls::Enabled(tls)) => tls.clone(),
+            _ => TlsOptions::default(),
+        };
+        if let Some(path) = &profile.tls_ca_file {
+            tls.ca_file_path = Some(path.clone());
+        }
+        if let Some(path) = &profile.tls_cert_key_file {
+            tls.cert_key_file_path = Some(path.clone());
+        }
+        if let Some(allow) = profile.tls_allow_invalid_certificates {
+            tls.allow_invalid_certificates = Some(allow);
+        }
+        #[cfg(feature = "openssl-tls")]
+        if let Some(allow) = profile.tls_allow_invalid_hostnames {
+            tls.allow_invalid_hostnames = Some(allow);
+        }
+        tls
+    }
+
+    pub fn attach(
+        &mut self,
+        client: Client,
+        server_info: ServerInfo,
+        read_only: bool,
+        tunnel: Option<Tunnel>,
+    ) {
+        self.client = Some(client);
+        self.server_info = Some(server_info);
+        self.read_only = read_only;
+        if let Some(previous) = std::mem::replace(&mut self.tunnel, tunnel) {
+            previous.close();
         }
     }
-    pub fn get_client(&self) -> Option<&Client> {
-        self.client.as_ref()
+
+    pub fn disconnect(&mut self) {
+        self.client = None;
+        self.server_info = None;
+        if let Some(tunnel) = self.tunnel.take() {
+            tunnel.close();
+        }
     }
-    pub fn get_server_info(&self) -> Option<ServerInfo> {
-        self.server_info.clone()
+
+    /// Why the SSH tunnel stopped, reported once after ssh exits.
+    pub fn closed_tunnel(&mut self) -> Option<String> {
+        let reason = self.tunnel.as_mut()?.closed_reason()?;
+        self.tunnel = None;
+        Some(reason)
+    }
+
+    pub fn query_service(&self) -> Option<QueryService> {
+        self.client.clone().map(|client| {
+            QueryService::new(client, self.audit_log()).with_read_only(self.read_only)
+        })
+    }
+    pub fn crud_service(&self) -> Option<CrudService> {
+        self.client
+            .clone()
+            .map(|client| CrudService::new(client, self.audit_log()).with_read_only(self.read_only))
+    }
+    // records commands against the connected host
+    fn audit_log(&self) -> AuditLog {
+        let host = self
+            .server_info
+            .as_ref()
+            .map(|info| format!("{}:{}", info.host, info.port))
+            .unwrap_or_default();
+        AuditLog::new(host)
     }
     fn parse_host_port(uri: &str) -> (String, u16) {
         if let Some(after_protocol) = uri
             .strip_prefix("mongodb://")
             .or_else(|| uri.strip_prefix("mongodb+srv://"))
+            && let Some(host_part) = after_protocol.split('/').next()
         {
-            if let Some(host_part) = after_protocol.split('/').next() {
-                let host_part = if let Some(at_pos) = host_part.rfind('@') {
-                    &host_part[at_pos + 1..]
-                } else {
-                    host_part
-                };
-
-                if let Some((host, port_str)) = host_part.split_once(':') {
-                    let port = port_str.parse().unwrap_or(27017);
-                    return (host.to_string(), port);
-                } else {
-                    return (host_part.to_string(), 27017);
-                }
+            let host_part = if let Some(at_pos) = host_part.rfind('@') {
+                &host_part[at_pos + 1..]
+            } else {
+                host_part
+            };
+
+            if let Some((host, port_str)) = host_part.split_once(':') {
+                let port = port_str.parse().unwrap_or(27017);
+                return (host.to_string(), port);
+            } else {
+                return (host_part.to_string(), 27017);
             }
         }
         ("localhost".to_string(), 27017)
@@ -0,0 +1,349 @@
+use futures::TryStreamExt;
+use mongodb::{
+    Client,
+    bson::{Bson, Document, doc, oid::ObjectId},
+    options::UpdateModifications,
+};
+
+use super::audit::{Affected, AuditEntry, AuditLog, namespace};
+use crate::error::AppError;
+
+/// Writes documents. Kept apart from `QueryService` so reads can never
+/// change data by accident.
+#[derive(Clone)]
+pub struct CrudService {
+    client: Client,
+    read_only: bool,
+    audit: AuditLog,
+}
+
+impl CrudService {
+    pub fn new(client: Client, audit: AuditLog) -> Self {
+        Self {
+            client,
+            read_only: false,
+            audit,
+        }
+    }
+
+    /// Makes every write fail with `AppError::ReadOnly` before it reaches
+    /// the server.
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    // checked before an audit entry is made: a refused write never reaches
+    // the server, so there is no command to log
+    fn check_writable(&self) -> Result<(), AppError> {
+        if self.read_only {
+            return Err(AppError::ReadOnly(
+                "writes are disabled for this connection".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Inserts `document` and returns its `_id`, generated here if the
+    /// document has none so the audit log names it before the insert.
+    pub async fn insert_document(
+        &self,
+        db_name: &str,
+        coll_name: &str,
+        document: Document,
+    ) -> Result<Bson, AppError> {
+        self.check_writable()?;
+        let document = if document.contains_key("_id") {
+            document
+        } else {
+            // first, where the driver would put it
+            let mut with_id = doc! { "_id": ObjectId::new() };
+            with_id.extend(document);
+            with_id
+        };
+        let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+        let entry =
+            AuditEntry::new(namespace(db_name, coll_name), "insertOne").detail("_id", id.clone());
+        let run = async {
+            let collection = self
+                .client
+                .database(db_name)
+                .collection::<Document>(coll_name);
+
+            let result = collection
+                .insert_one(document)
+                .await
+                .map_err(|e| AppError::Query(format!("Failed to insert document: {}", e)))?;
+
+            Ok(result.inserted_id)
+        };
+        self.audit
+            .track(entry, |_: &Bson| Affected::Inserted(id), run)
+            .await
+    }
+
+    /// Deletes the first document matching `filter` and returns how many
+    /// were deleted.
+    pub async fn delete_document(
+        &self,
+        db_name: &str,
+        coll_name: &str,
+        filter: Document,
+    ) -> Result<u64, AppError> {
+        self.check_writable()?;
+        let entry =
+            AuditEntry::new(namespace(db_name, coll_name), "deleteOne").filter(Some(&filter));
+        let run = async {
+            let collection = self
+                .client
+                .database(db_name)
+                .collection::<Document>(coll_name);
+
+            let result = collection
+                .delete_one(filter)
+                .await
+                .map_err(|e| AppError::Query(format!("Failed to delete document: {}", e)))?;
+
+            Ok(result.deleted_count)
+        };
+        self.audit
+            .track(entry, |deleted: &u64| Affected::Documents(*deleted), run)
+            .await
+    }
+
+    /// Deletes every document matching `filter` and returns how many were
+    /// deleted, together with the deleted documents if there were at most
+    /// `keep` of them. The documents come back even if the delete fails,
+    /// as it may have removed some of them before failing.
+    pub async fn delete_documents(
+        &self,
+        db_name: &str,
+        coll_name: &str,
+        filter: Document,
+        keep: usize,
+    ) -> (Option<Vec<Document>>, Result<u64, AppError>) {
+        if let Err(e) = self.check_writable() {
+            return (None, Err(e));
+        }
+        let (filter, previous) = match self
+            .previous_versions(db_name, coll_name, filter, keep)
+            .await
+        {
+            Ok(read) => read,
+            Err(e) => return (None, Err(e)),
+        };
+
+        let entry =
+            AuditEntry::new(namespace(db_name, coll_name), "deleteMany").filter(Some(&filter));
+        let run = async {
+            let collection = self
+                .client
+                .database(db_name)
+                .collection::<Document>(coll_name);
+            let result = collection
+                .delete_many(filter)
+                .await
+                .map_err(|e| AppError::Query(format!("Failed to delete documents: {}", e)))?;
+            Ok(result.deleted_count)
+        };
+        let result = self
+            .audit
+            .track(entry, |deleted: &u64| Affected::Documents(*deleted), run)
+            .await;
+        (previous, result)
+    }
+
+    /// Applies `update` to every document matching `filter` and returns the
+    /// matched and modified counts, together with the documents as they
+    /// were before the update if there were at most `keep` of them. As with
+    /// `delete_documents`, those come back even if the update fails.
+    pub async fn update_documents(
+        &self,
+        db_name: &str,
+        coll_name: &str,
+        filter: Document,
+        update: UpdateModifications,
+        keep: usize,
+    ) -> (Option<Vec<Document>>, Result<(u64, u64), AppError>) {
+        if let Err(e) = self.check_writable() {
+            return (None, Err(e));
+        }
+        let (filter, previous) = match self
+            .previous_versions(db_name, coll_name, filter, keep)
+            .await
+        {
+            Ok(read) => read,
+            Err(e) => return (None, Err(e)),
+        };
+
+        let shown = match &update {
+            UpdateModifications::Document(update) => Bson::Document(update.clone()),
+            UpdateModifications::Pipeline(stages) => {
+                stages.iter().cloned().map(Bson::Document).collect()
+            }
+            _ => Bson::Null,
+        };
+        let entry = AuditEntry::new(namespace(db_name, coll_name), "updateMany")
+            .filter(Some(&filter))
+            .detail("update", shown);
+        let run = async {
+            let collection = self
+                .client
+                .database(db_name)
+                .collection::<Document>(coll_name);
+            let result = collection
+                .update_many(filter, update)
+                .await
+                .map_err(|e| AppError::Query(format!("Failed to update documents: {}", e)))?;
+            Ok((result.matched_count, result.modified_count))
+        };
+        let result = self
+            .audit
+            .track(
+                entry,
+                |(matched, modified): &(u64, u64)| Affected::Updated {
+                    matched: *matched,
+                    modified: *modified,
+                },
+                run,
+            )
+            .await;
+        (previous, result)
+    }
+
+    /// Writes `documents` back by `_id`, inserting the ones that no longer
+    /// exist, and returns how many were restored.
+    pub async fn restore_documents(
+        &self,
+        db_name: &str,
+        coll_name: &str,
+        documents: Vec<Document>,
+    ) -> Result<u64, AppError> {
+        self.check_writable()?;
+        let ids: Vec<Bson> = documents
+            .iter()
+            .filter_map(|document| document.get("_id").cloned())
+            .collect();
+        let entry = AuditEntry::new(namespace(db_name, coll_name), "restore")
+            .filter(Some(&doc! { "_id": { "$in": ids } }));
+        let run = async {
+            let collection = self
+                .client
+                .database(db_name)
+                .collection::<Document>(coll_name);
+
+            let mut restored = 0;
+            for document in documents {
+                let Some(id) = document.get("_id").cloned() else {
+                    continue;
+                };
+                collection
+                    .replace_one(doc! { "_id": id }, document)
+                    .upsert(true)
+                    .await
+                    .map_err(|e| AppError::Query(format!("Failed to restore document: {}", e)))?;
+                restored += 1;
+            }
+            Ok(restored)
+        };
+        self.audit
+            .track(entry, |restored: &u64| Affected::Documents(*restored), run)
+            .await
+    }
+
+    // reads the documents a write to `filter` is about to change, if there
+    // are at most `keep`, and narrows the filter to exactly those documents
+    // so the write cannot touch any that were not read
+    async fn previous_versions(
+        &self,
+        db_name: &str,
+        coll_name: &str,
+        filter: Document,
+        keep: usize,
+    ) -> Result<(Document, Option<Vec<Document>>), AppError> {
+        let entry = AuditEntry::new(namespace(db_name, coll_name), "find").filter(Some(&filter));
+        let run = async {
+            let collection = self
+                .client
+                .database(db_name)
+                .collection::<Document>(coll_name);
+
+            let documents: Vec<Document> = collection
+                .find(filter.clone())
+                .limit(keep as i64 + 1)
+                .await
+                .map_err(|e| AppError::Query(format!("Failed to read documents: {}", e)))?
+                .try_collect()
+                .await
+                .map_err(|e| AppError::Query(format!("Failed to read documents: {}", e)))?;
+            if documents.len() > keep {
+                return Ok((filter, None));
+            }
+
+            let ids: Vec<Bson> = documents
+                .iter()
+                .filter_map(|document| document.get("_id").cloned())
+                .collect();
+            let filter = doc! { "$and": [filter, { "_id": { "$in": ids } }] };
+            Ok((filter, Some(documents)))
+        };
+        self.audit
+            .track(
+                entry,
+                |(_, previous): &(Document, Option<Vec<Document>>)| {
+                    previous.as_ref().map_or(Affected::Nothing, |previous| {
+                        Affected::Documents(previous.len() as u64)
+                    })
+                },
+                run,
+            )
+            .await
+    }
+
+    /// Replaces `original` with `replacement`, matching on `_id` and on the
+    /// whole stored document, so the write fails if anyone changed the
+    /// document since `original` was read.
+    pub async fn replace_document(
+        &self,
+        db_name: &str,
+        coll_name: &str,
+        original: &Document,
+        replacement: Document,
+    ) -> Result<(), AppError> {
+        self.check_writable()?;
+        let id_filter = original.get("_id").map(|id| doc! { "_id": id.clone() });
+        let entry = AuditEntry::new(namespace(db_name, coll_name), "replaceOne")
+            .filter(id_filter.as_ref())
+            .detail("replacement", Bson::Document(replacement.clone()));
+        let run = async {
+            let id = original
+                .get("_id")
+                .cloned()
+                .ok_or_else(|| AppError::InvalidInput("Document has no _id".to_string()))?;
+            let collection = self
+                .client
+                .database(db_name)
+                .collection::<Document>(coll_name);
+
+            let filter = doc! {
+                "_id": id,
+                "$expr": { "$eq": ["$$ROOT", { "$literal": original.clone() }] },
+            };
+            let result = collection
+                .replace_one(filter, replacement)
+                .await
+                .map_err(|e| AppError::Query(format!("Failed to replace document: {}", e)))?;
+
+            if result.matched_count == 0 {
+                return Err(AppError::Query(
+                    "The document was changed or deleted since it was opened; reload and edit it again"
+                        .to_string(),
+                ));
+            }
+            Ok(())
+        };
+        self.audit
+            .track(entry, |_: &()| Affected::Documents(1), run)
+            .await
+    }
+}
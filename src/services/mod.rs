@@ -1,2 +1,5 @@
+pub mod audit;
 pub mod connection;
-pub mod query;
\ No newline at end of file
+pub mod crud;
+pub mod query;
+pub mod tunnel;
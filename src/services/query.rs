@@ -1,125 +1,399 @@
-use mongodb::{Client, bson::Document};
+use std::time::Instant;
 
+use futures::TryStreamExt;
+use mongodb::{
+    Client,
+    bson::{Bson, Document, doc},
+};
+
+use super::audit::{Affected, AuditEntry, AuditLog, namespace};
 use crate::{
     error::AppError,
-    models::{CollectionInfo, DatabaseInfo},
+    models::{CollectionInfo, DatabaseInfo, Keyset, QueryParams, QueryResult, StreamBatch},
 };
 
+/// Aggregation stages that write their input to a collection.
+pub const WRITE_STAGES: [&str; 2] = ["$out", "$merge"];
+
+#[derive(Clone)]
 pub struct QueryService {
     client: Client,
+    comment: Option<String>,
+    read_only: bool,
+    audit: AuditLog,
 }
 
 impl QueryService {
-    pub fn new(client: Client) -> Self {
-        Self { client }
+    pub fn new(client: Client, audit: AuditLog) -> Self {
+        Self {
+            client,
+            comment: None,
+            read_only: false,
+            audit,
+        }
+    }
+
+    /// Rejects aggregations that would write, i.e. ones with `$out` or
+    /// `$merge` stages.
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Tags find, count and aggregate commands with `comment` so they can be
+    /// located in `$currentOp` and killed.
+    pub fn with_comment(mut self, comment: String) -> Self {
+        self.comment = Some(comment);
+        self
     }
 
     pub async fn list_databases(&self) -> Result<Vec<DatabaseInfo>, AppError> {
-        let databases = self
-            .client
-            .list_databases()
+        let entry = AuditEntry::new("admin", "listDatabases");
+        let run = async {
+            let databases =
+                self.client.list_databases().await.map_err(|e| {
+                    AppError::Connection(format!("Failed to list databases: {}", e))
+                })?;
+
+            let mut db_infos = Vec::new();
+            for db in databases {
+                let collection_count = self
+                    .client
+                    .database(&db.name)
+                    .list_collection_names()
+                    .await
+                    .map(|collections| collections.len())
+                    .unwrap_or(0);
+                let db_info = DatabaseInfo {
+                    name: db.name,
+                    size_on_disk: db.size_on_disk,
+                    collection_count,
+                    empty: db.empty,
+                };
+                db_infos.push(db_info);
+            }
+
+            Ok(db_infos)
+        };
+        self.audit
+            .track(
+                entry,
+                |dbs: &Vec<DatabaseInfo>| Affected::Listed(dbs.len() as u64),
+                run,
+            )
             .await
-            .map_err(|e| AppError::Connection(format!("Failed to list databases: {}", e)))?;
+    }
 
-        let mut db_infos = Vec::new();
-        for db in databases {
-            let collection_count = self
-                .client
-                .database(&db.name)
+    pub async fn list_collections(&self, db: &str) -> Result<Vec<CollectionInfo>, AppError> {
+        let entry = AuditEntry::new(db, "listCollections");
+        let run = async {
+            let database = self.client.database(db);
+            let collections = database
                 .list_collection_names()
                 .await
-                .map(|collections| collections.len())
-                .unwrap_or(0);
-            let db_info = DatabaseInfo {
-                name: db.name,
-                size_on_disk: db.size_on_disk as u64,
-                collection_count,
-                empty: db.empty,
-            };
-            db_infos.push(db_info);
-        }
+                .map_err(|e| AppError::Query(format!("Failed to list collections: {}", e)))?;
+
+            let mut coll_infos = Vec::new();
+            for coll_name in collections {
+                let collection = database.collection::<Document>(&coll_name);
+
+                let doc_count = collection.estimated_document_count().await.unwrap_or(0);
 
-        Ok(db_infos)
+                let indexes = collection.list_index_names().await.unwrap_or_default();
+
+                let coll_info = CollectionInfo {
+                    name: coll_name,
+                    document_count: doc_count,
+                    size: 0,
+                    indexes,
+                    capped: false,
+                };
+                coll_infos.push(coll_info);
+            }
+            Ok(coll_infos)
+        };
+        self.audit
+            .track(
+                entry,
+                |colls: &Vec<CollectionInfo>| Affected::Listed(colls.len() as u64),
+                run,
+            )
+            .await
     }
 
-    pub async fn list_collections(&self, db: &str) -> Result<Vec<CollectionInfo>, AppError> {
-        let database = self.client.database(db);
-        let collections = database
-            .list_collection_names()
+    pub async fn find_documents(&self, params: &QueryParams) -> Result<Vec<Document>, AppError> {
+        let entry = AuditEntry::new(namespace(&params.database, &params.collection), "find")
+            .filter(params.filter.as_ref());
+        let run = async {
+            let coll = self
+                .client
+                .database(&params.database)
+                .collection::<Document>(&params.collection);
+
+            let filter_doc = params.filter.clone().unwrap_or_default();
+
+            let mut find = coll
+                .find(filter_doc)
+                .skip(params.skip)
+                .limit(params.limit)
+                .sort(params.sort.clone().unwrap_or_default())
+                .projection(params.projection.clone().unwrap_or_default());
+            if let Some(comment) = &self.comment {
+                find = find.comment(comment.clone());
+            }
+
+            let mut cursor = find
+                .await
+                .map_err(|e| AppError::Query(format!("Failed to find documents: {}", e)))?;
+
+            let mut documents = Vec::new();
+            use futures::stream::StreamExt;
+            while let Some(result) = cursor.next().await {
+                match result {
+                    Ok(doc) => documents.push(doc),
+                    Err(e) => {
+                        return Err(AppError::Query(format!("Error reading documents: {}", e)));
+                    }
+                }
+            }
+            Ok(documents)
+        };
+        self.audit
+            .track(
+                entry,
+                |found: &Vec<Document>| Affected::Documents(found.len() as u64),
+                run,
+            )
             .await
-            .map_err(|e| AppError::Query(format!("Failed to list collections: {}", e)))?;
+    }
 
-        let mut coll_infos = Vec::new();
-        for coll_name in collections {
-            let collection = database.collection::<Document>(&coll_name);
+    /// Fetches the full document with `id`, ignoring any projection.
+    pub async fn find_by_id(
+        &self,
+        db_name: &str,
+        coll_name: &str,
+        id: Bson,
+    ) -> Result<Option<Document>, AppError> {
+        let entry = AuditEntry::new(namespace(db_name, coll_name), "findOne")
+            .filter(Some(&doc! { "_id": id.clone() }));
+        let run = async {
+            let collection = self
+                .client
+                .database(db_name)
+                .collection::<Document>(coll_name);
 
-            let doc_count = collection.estimated_document_count().await.unwrap_or(0);
+            let mut find = collection.find_one(doc! { "_id": id });
+            if let Some(comment) = &self.comment {
+                find = find.comment(comment.clone());
+            }
+            find.await
+                .map_err(|e| AppError::Query(format!("Failed to fetch document: {}", e)))
+        };
+        self.audit
+            .track(
+                entry,
+                |found: &Option<Document>| Affected::Documents(found.is_some() as u64),
+                run,
+            )
+            .await
+    }
 
-            let indexes = collection.list_index_names().await.unwrap_or_default();
+    /// Fetches one page of documents together with the total number of
+    /// documents matching the filter.
+    pub async fn find_page(&self, params: &QueryParams) -> Result<QueryResult, AppError> {
+        let started = Instant::now();
+        let (document, total_count) = futures::try_join!(
+            self.find_documents(params),
+            self.count_documents(&params.database, &params.collection, params.filter.clone()),
+        )?;
 
-            let coll_info = CollectionInfo {
-                name: coll_name,
-                document_count: doc_count,
-                size: 0,
-                indexes,
-                capped: false,
-            };
-            coll_infos.push(coll_info);
-        }
-        Ok(coll_infos)
+        Ok(QueryResult {
+            document,
+            total_count,
+            execution_time: started.elapsed(),
+        })
     }
 
-    pub async fn find_documents(
+    /// Reads up to `params.limit` documents in `_id` order starting at
+    /// `keyset`; `params.sort` and `params.skip` are ignored. Batches are
+    /// always returned in ascending `_id` order.
+    pub async fn find_keyset(
         &self,
-        db: &str,
-        collection: &str,
-        filter: Option<Document>,
-        skip: u64,
-        limit: i64,
+        params: &QueryParams,
+        keyset: Keyset,
     ) -> Result<Vec<Document>, AppError> {
-        let coll = self.client.database(db).collection::<Document>(collection);
+        let entry = AuditEntry::new(namespace(&params.database, &params.collection), "find")
+            .filter(params.filter.as_ref());
+        let run = async {
+            let coll = self
+                .client
+                .database(&params.database)
+                .collection::<Document>(&params.collection);
+
+            let (bound, sort) = match &keyset {
+                Keyset::First | Keyset::Offset(_) => (None, doc! { "_id": 1 }),
+                Keyset::Last | Keyset::OffsetFromEnd(_) => (None, doc! { "_id": -1 }),
+                Keyset::After(id) => (
+                    Some(doc! { "_id": { "$gt": id.clone() } }),
+                    doc! { "_id": 1 },
+                ),
+                Keyset::Before(id) => (
+                    Some(doc! { "_id": { "$lt": id.clone() } }),
+                    doc! { "_id": -1 },
+                ),
+            };
+            let filter = params.filter.clone().filter(|filter| !filter.is_empty());
+            let filter_doc = match (filter, bound) {
+                (Some(filter), Some(bound)) => doc! { "$and": [filter, bound] },
+                (filter, bound) => filter.or(bound).unwrap_or_default(),
+            };
 
-        let filter_doc = filter.unwrap_or_else(|| Document::new());
+            // the next batch is located by _id, so it must stay in the projection
+            let mut projection = params.projection.clone().unwrap_or_default();
+            if projection.get("_id").is_some_and(|id| !is_truthy(id)) {
+                projection.remove("_id");
+            }
+
+            let mut find = coll
+                .find(filter_doc)
+                .sort(sort)
+                .limit(params.limit)
+                .projection(projection);
+            if let Keyset::Offset(skip) | Keyset::OffsetFromEnd(skip) = keyset {
+                find = find.skip(skip);
+            }
+            if let Some(comment) = &self.comment {
+                find = find.comment(comment.clone());
+            }
+
+            let mut documents: Vec<Document> = find
+                .await
+                .map_err(|e| AppError::Query(format!("Failed to find documents: {}", e)))?
+                .try_collect()
+                .await
+                .map_err(|e| AppError::Query(format!("Error reading documents: {}", e)))?;
 
-        let mut cursor = coll
-            .find(filter_doc)
-            .skip(skip)
-            .limit(limit)
+            if matches!(
+                keyset,
+                Keyset::Last | Keyset::Before(_) | Keyset::OffsetFromEnd(_)
+            ) {
+                documents.reverse();
+            }
+            Ok(documents)
+        };
+        self.audit
+            .track(
+                entry,
+                |found: &Vec<Document>| Affected::Documents(found.len() as u64),
+                run,
+            )
             .await
-            .map_err(|e| AppError::Query(format!("Failed to find documents: {}", e)))?;
-
-        let mut documents = Vec::new();
-        use futures::stream::StreamExt;
-        while let Some(result) = cursor.next().await {
-            match result {
-                Ok(doc) => documents.push(doc),
-                Err(e) => {
-                    return Err(AppError::Query(format!(
-                        "Error reading aggregation result: {}",
-                        e
-                    )));
-                }
+    }
+
+    /// Reads a streamed batch at `keyset`, together with the filtered total
+    /// and the `_id` at the other end when the stream is being (re)started
+    /// from either end.
+    pub async fn find_stream_batch(
+        &self,
+        params: &QueryParams,
+        keyset: Keyset,
+    ) -> Result<StreamBatch, AppError> {
+        let far_end = match keyset {
+            Keyset::First => Keyset::Last,
+            Keyset::Last => Keyset::First,
+            _ => {
+                return Ok(StreamBatch {
+                    documents: self.find_keyset(params, keyset).await?,
+                    total_count: None,
+                    far_end_id: None,
+                });
             }
+        };
+
+        let mut end_params = params.clone();
+        end_params.limit = 1;
+        end_params.projection = Some(doc! { "_id": 1 });
+        let (documents, total_count, end) = futures::try_join!(
+            self.find_keyset(params, keyset),
+            self.count_documents(&params.database, &params.collection, params.filter.clone()),
+            self.find_keyset(&end_params, far_end),
+        )?;
+        Ok(StreamBatch {
+            documents,
+            total_count: Some(total_count),
+            far_end_id: end
+                .first()
+                .and_then(|document| document.get("_id"))
+                .cloned(),
+        })
+    }
+
+    /// Builds a case-insensitive search for `term` across the fields of a
+    /// sample document, used by the live `f` search mode.
+    pub async fn search_filter(
+        &self,
+        db: &str,
+        collection: &str,
+        term: &str,
+    ) -> Result<Option<Document>, AppError> {
+        // Get a sample document to extract field names
+        let mut sample_query = QueryParams::new(db, collection);
+        sample_query.limit = 1;
+        let sample_docs = self
+            .find_documents(&sample_query)
+            .await
+            .map_err(|e| AppError::Query(format!("Failed to analyze fields: {}", e)))?;
+
+        let Some(sample_doc) = sample_docs.first() else {
+            return Ok(None);
+        };
+
+        // Build $or array with regex for each field
+        let or_conditions: Vec<Document> = sample_doc
+            .keys()
+            .filter(|key| *key != "_id")
+            .map(|key| doc! { key: {"$regex": term, "$options": "i"} })
+            .collect();
+
+        if or_conditions.is_empty() {
+            return Ok(None);
         }
-        Ok(documents)
+        Ok(Some(doc! { "$or": or_conditions }))
     }
 
+    /// Counts documents matching `filter`. Without a filter the collection
+    /// metadata count is used, which avoids a full scan on large collections.
     pub async fn count_documents(
         &self,
         db: &str,
         collection: &str,
         filter: Option<Document>,
     ) -> Result<u64, AppError> {
-        let coll = self.client.database(db).collection::<Document>(collection);
+        let entry = AuditEntry::new(namespace(db, collection), "count").filter(filter.as_ref());
+        let run = async {
+            let coll = self.client.database(db).collection::<Document>(collection);
+
+            let Some(filter_doc) = filter.filter(|filter| !filter.is_empty()) else {
+                return coll
+                    .estimated_document_count()
+                    .await
+                    .map_err(|e| AppError::Query(format!("Failed to count documents: {}", e)));
+            };
 
-        let filter_doc = filter.unwrap_or_else(|| Document::new());
+            let mut count = coll.count_documents(filter_doc);
+            if let Some(comment) = &self.comment {
+                count = count.comment(comment.clone());
+            }
 
-        let count = coll
-            .count_documents(filter_doc)
-            .await
-            .map_err(|e| AppError::Query(format!("Failed to count documents: {}", e)))?;
+            let count = count
+                .await
+                .map_err(|e| AppError::Query(format!("Failed to count documents: {}", e)))?;
 
-        Ok(count)
+            Ok(count)
+        };
+        self.audit
+            .track(entry, |count: &u64| Affected::Documents(*count), run)
+            .await
     }
 
     pub async fn aggregate(
@@ -128,27 +402,103 @@ impl QueryService {
         collection: &str,
         pipeline: Vec<Document>,
     ) -> Result<Vec<Document>, AppError> {
-        let coll = self.client.database(db).collection::<Document>(collection);
+        if self.read_only
+            && let Some(stage) = pipeline
+                .iter()
+                .find_map(|stage| WRITE_STAGES.iter().find(|op| stage.contains_key(**op)))
+        {
+            return Err(AppError::ReadOnly(format!(
+                "{} stages are not allowed",
+                stage
+            )));
+        }
 
-        let mut cursor = coll
-            .aggregate(pipeline)
+        let stages = pipeline.iter().cloned().map(Bson::Document).collect();
+        let entry = AuditEntry::new(namespace(db, collection), "aggregate")
+            .detail("pipeline", Bson::Array(stages));
+        let run = async {
+            let coll = self.client.database(db).collection::<Document>(collection);
+
+            let mut aggregate = coll.aggregate(pipeline);
+            if let Some(comment) = &self.comment {
+                aggregate = aggregate.comment(comment.clone());
+            }
+
+            let mut cursor = aggregate
+                .await
+                .map_err(|e| AppError::Query(format!("Aggregation failed: {}", e)))?;
+
+            let mut documents = Vec::new();
+            use futures::stream::StreamExt;
+            while let Some(result) = cursor.next().await {
+                match result {
+                    Ok(doc) => documents.push(doc),
+                    Err(e) => {
+                        return Err(AppError::Query(format!(
+                            "Error reading aggregation result: {}",
+                            e
+                        )));
+                    }
+                }
+            }
+
+            Ok(documents)
+        };
+        self.audit
+            .track(
+                entry,
+                |found: &Vec<Document>| Affected::Documents(found.len() as u64),
+                run,
+            )
             .await
-            .map_err(|e| AppError::Query(format!("Aggregation failed: {}", e)))?;
-
-        let mut documents = Vec::new();
-        use futures::stream::StreamExt;
-        while let Some(result) = cursor.next().await {
-            match result {
-                Ok(doc) => documents.push(doc),
-                Err(e) => {
-                    return Err(AppError::Query(format!(
-                        "Error reading aggregation result: {}",
-                        e
-                    )));
+    }
+
+    /// Kills every operation on the server that was tagged with `comment`,
+    /// including `getMore`s issued for its cursors.
+    pub async fn kill_operations(&self, comment: &str) -> Result<(), AppError> {
+        let entry =
+            AuditEntry::new("admin", "killOp").detail("comment", Bson::String(comment.to_string()));
+        let run = async {
+            let admin = self.client.database("admin");
+            let pipeline = vec![
+                doc! { "$currentOp": {} },
+                doc! { "$match": { "$or": [
+                    { "command.comment": comment },
+                    { "cursor.originatingCommand.comment": comment },
+                ] } },
+            ];
+
+            let mut cursor = admin
+                .aggregate(pipeline)
+                .await
+                .map_err(|e| AppError::Query(format!("Failed to list operations: {}", e)))?;
+
+            use futures::stream::StreamExt;
+            while let Some(result) = cursor.next().await {
+                let op = result
+                    .map_err(|e| AppError::Query(format!("Failed to list operations: {}", e)))?;
+                if let Some(opid) = op.get("opid") {
+                    admin
+                        .run_command(doc! { "killOp": 1, "op": opid.clone() })
+                        .await
+                        .map_err(|e| AppError::Query(format!("Failed to kill operation: {}", e)))?;
                 }
             }
-        }
+            Ok(())
+        };
+        self.audit
+            .track(entry, |_: &()| Affected::Nothing, run)
+            .await
+    }
+}
 
-        Ok(documents)
+// projection values like 0, false and 0.0 exclude a field
+fn is_truthy(value: &Bson) -> bool {
+    match value {
+        Bson::Boolean(b) => *b,
+        Bson::Int32(n) => *n != 0,
+        Bson::Int64(n) => *n != 0,
+        Bson::Double(n) => *n != 0.0,
+        _ => true,
     }
 }
@@ -0,0 +1,207 @@
+use std::{
+    net::TcpListener,
+    process::{ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::AsyncReadExt,
+    net::TcpStream,
+    process::{Child, Command},
+};
+
+use crate::{config::SshTunnel, error::AppError};
+
+// how often to check whether the forward is listening yet
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// local ports tried before a forward failure is reported
+const FORWARD_ATTEMPTS: u32 = 3;
+
+/// A local port forwarded to a MongoDB server through `ssh -N -L`. The ssh
+/// process is killed when the tunnel is closed or dropped.
+pub struct Tunnel {
+    child: Child,
+    local_port: u16,
+    label: String,
+    // what ssh printed, which explains why it exited
+    stderr: Arc<Mutex<String>>,
+}
+
+// how the ssh process ended up after it was started
+enum Attempt {
+    Open(Tunnel),
+    Exited(Tunnel, ExitStatus),
+}
+
+impl Tunnel {
+    /// Starts ssh with a forward from a free local port to `target_host`
+    /// and waits until the forward accepts connections. ssh runs in batch
+    /// mode, so keys must be unlocked in the agent and the host key known.
+    ///
+    /// The port is only known to be free until it is handed to ssh, so
+    /// when ssh cannot listen on it another one is tried.
+    pub async fn open(
+        config: &SshTunnel,
+        target_host: &str,
+        target_port: u16,
+        timeout: Duration,
+    ) -> Result<Self, AppError> {
+        let target_host = if target_host.contains(':') {
+            format!("[{}]", target_host)
+        } else {
+            target_host.to_string()
+        };
+        let deadline = Instant::now() + timeout;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let local_port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+            let forward = format!("127.0.0.1:{}:{}:{}", local_port, target_host, target_port);
+            match Self::start(config, &forward, local_port, deadline).await? {
+                Attempt::Open(tunnel) => return Ok(tunnel),
+                Attempt::Exited(tunnel, _)
+                    if tunnel.forward_failed() && attempts < FORWARD_ATTEMPTS => {}
+                Attempt::Exited(tunnel, status) => {
+                    return Err(AppError::Connection(format!(
+                        "SSH tunnel through {} failed: {}",
+                        tunnel.label,
+                        tunnel.exit_detail(status)
+                    )));
+                }
+            }
+        }
+    }
+
+    // runs ssh with the `-L` argument `forward` until the forward listens
+    // or ssh exits
+    async fn start(
+        config: &SshTunnel,
+        forward: &str,
+        local_port: u16,
+        deadline: Instant,
+    ) -> Result<Attempt, AppError> {
+        let mut command = Command::new("ssh");
+        command
+            .args([
+                "-N",
+                "-o",
+                "BatchMode=yes",
+                "-o",
+                "ExitOnForwardFailure=yes",
+            ])
+            .args(["-o", "ServerAliveInterval=30", "-o", "LogLevel=ERROR"])
+            .arg("-L")
+            .arg(forward);
+        if let Some(port) = config.port {
+            command.arg("-p").arg(port.to_string());
+        }
+        if let Some(key_file) = &config.key_file {
+            command
+                .arg("-i")
+                .arg(key_file)
+                .args(["-o", "IdentitiesOnly=yes"]);
+        }
+        if let Some(user) = &config.user {
+            command.arg("-l").arg(user);
+        }
+        // nothing after this is read as an option
+        command
+            .arg("--")
+            .arg(&config.host)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| AppError::Connection(format!("Failed to start ssh: {}", e)))?;
+        let stderr = Arc::new(Mutex::new(String::new()));
+        let reader = child.stderr.take().map(|mut pipe| {
+            let stderr = Arc::clone(&stderr);
+            tokio::spawn(async move {
+                let mut buffer = [0; 1024];
+                loop {
+                    match pipe.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => stderr
+                            .lock()
+                            .unwrap()
+                            .push_str(&String::from_utf8_lossy(&buffer[..read])),
+                    }
+                }
+            })
+        });
+
+        let mut tunnel = Self {
+            child,
+            local_port,
+            label: config.label(),
+            stderr,
+        };
+        loop {
+            if let Some(status) = tunnel.child.try_wait()? {
+                // ssh has exited, so its stderr ends soon
+                if let Some(reader) = reader {
+                    let _ = reader.await;
+                }
+                return Ok(Attempt::Exited(tunnel, status));
+            }
+            if TcpStream::connect(("127.0.0.1", local_port)).await.is_ok() {
+                return Ok(Attempt::Open(tunnel));
+            }
+            if Instant::now() > deadline {
+                return Err(AppError::Connection(format!(
+                    "SSH tunnel through {} timed out",
+                    tunnel.label
+                )));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    pub fn local_port(&self) -> u16 {
+        self.local_port
+    }
+
+    /// `None` while ssh runs, otherwise why it stopped.
+    pub fn closed_reason(&mut self) -> Option<String> {
+        match self.child.try_wait() {
+            Ok(None) => None,
+            Ok(Some(status)) => Some(format!(
+                "SSH tunnel through {} closed: {}",
+                self.label,
+                self.exit_detail(status)
+            )),
+            Err(e) => Some(format!("SSH tunnel through {} failed: {}", self.label, e)),
+        }
+    }
+
+    pub fn close(mut self) {
+        let _ = self.child.start_kill();
+    }
+
+    // whether ssh gave up because it could not listen on the local port
+    fn forward_failed(&self) -> bool {
+        let stderr = self.stderr.lock().unwrap();
+        stderr.contains("Could not request local forwarding")
+            || stderr.contains("cannot listen to port")
+    }
+
+    // ssh's messages on one line, or the exit status if it printed none
+    fn exit_detail(&self, status: ExitStatus) -> String {
+        let stderr = self.stderr.lock().unwrap();
+        let lines: Vec<&str> = stderr
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        if lines.is_empty() {
+            status.to_string()
+        } else {
+            lines.join("; ")
+        }
+    }
+}
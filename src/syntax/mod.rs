@@ -0,0 +1,8 @@
+//! Reading and writing documents in the notations MongoDB users type and
+//! copy around: mongosh syntax and Extended JSON.
+
+pub mod parser;
+pub mod printer;
+
+pub use parser::{parse_document, parse_value};
+pub use printer::{DisplayMode, summary, to_compact_string, to_pretty_string};
@@ -0,0 +1,814 @@
+use std::fmt;
+
+use mongodb::bson::{Bson, DateTime, Document, Regex, oid::ObjectId};
+use serde_json::json;
+
+/// Keys marking a document as an Extended JSON value rather than a
+/// document.
+pub const EXTJSON_KEYS: [&str; 16] = [
+    "$oid",
+    "$date",
+    "$numberInt",
+    "$numberLong",
+    "$numberDouble",
+    "$numberDecimal",
+    "$binary",
+    "$uuid",
+    "$timestamp",
+    "$regularExpression",
+    "$symbol",
+    "$code",
+    "$minKey",
+    "$maxKey",
+    "$dbPointer",
+    "$undefined",
+];
+
+/// Syntax error with a 1-based position in the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses a value written in mongosh syntax or Extended JSON, e.g.
+/// `{_id: ObjectId("..."), at: {$gte: ISODate("2024-01-01")}, name: /^a/i}`.
+///
+/// Plain JSON is accepted as well. Unlike `serde_json`, key order is kept,
+/// which matters for sort specifications and pipeline stages.
+pub fn parse_value(input: &str) -> Result<Bson, ParseError> {
+    let mut parser = Parser::new(input);
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.peek().is_some() {
+        return Err(parser.error("unexpected text after the value"));
+    }
+    Ok(value)
+}
+
+/// Like [`parse_value`], but the input must be a document.
+pub fn parse_document(input: &str) -> Result<Document, ParseError> {
+    let mut parser = Parser::new(input);
+    parser.skip_whitespace();
+    if parser.peek() != Some('{') {
+        return Err(parser.error("expected a document starting with '{'"));
+    }
+    match parse_value(input)? {
+        Bson::Document(doc) => Ok(doc),
+        _ => Err(parser.error("expected a document, not an Extended JSON value")),
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(input: &str) -> Self {
+        Self {
+            chars: input.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        self.error_at(self.pos, message)
+    }
+
+    fn error_at(&self, pos: usize, message: impl Into<String>) -> ParseError {
+        let before = &self.chars[..pos.min(self.chars.len())];
+        let line = before.iter().filter(|c| **c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|c| **c != '\n').count() + 1;
+        ParseError {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    // skips whitespace and `//` or `/* */` comments
+    fn skip_whitespace(&mut self) {
+        loop {
+            match (self.peek(), self.chars.get(self.pos + 1)) {
+                (Some(c), _) if c.is_whitespace() => self.pos += 1,
+                (Some('/'), Some('/')) => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    self.pos += 2;
+                    while self.peek().is_some()
+                        && !(self.peek() == Some('*') && self.chars.get(self.pos + 1) == Some(&'/'))
+                    {
+                        self.pos += 1;
+                    }
+                    self.pos = (self.pos + 2).min(self.chars.len());
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected '{}', found '{}'", expected, c))),
+            None => Err(self.error(format!("expected '{}', found end of input", expected))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Bson, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.document(),
+            Some('[') => self.array(),
+            Some(quote @ ('"' | '\'')) => Ok(Bson::String(self.string(quote)?)),
+            Some('/') => self.regex(),
+            Some(c) if c == '-' || c == '+' || c == '.' || c.is_ascii_digit() => self.number(),
+            Some(c) if is_identifier_start(c) => self.identifier_value(),
+            Some(c) => Err(self.error(format!("unexpected character '{}'", c))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn document(&mut self) -> Result<Bson, ParseError> {
+        let start = self.pos;
+        self.expect('{')?;
+        let mut doc = Document::new();
+
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('}') => {
+                    self.pos += 1;
+                    break;
+                }
+                None => return Err(self.error("unclosed '{'")),
+                _ => {}
+            }
+
+            let key = self.key()?;
+            self.expect(':')?;
+            let value = self.value()?;
+            doc.insert(key, value);
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {}
+                Some(c) => return Err(self.error(format!("expected ',' or '}}', found '{}'", c))),
+                None => return Err(self.error("unclosed '{'")),
+            }
+        }
+
+        if doc.keys().any(|key| EXTJSON_KEYS.contains(&key.as_str())) {
+            return from_extjson(Bson::Document(doc).into_relaxed_extjson())
+                .map_err(|e| self.error_at(start, e));
+        }
+        Ok(Bson::Document(doc))
+    }
+
+    fn key(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(quote @ ('"' | '\'')) => self.string(quote),
+            Some(c) if is_identifier_start(c) => Ok(self.identifier()),
+            Some(c) if c.is_ascii_digit() => Ok(self.identifier()),
+            Some(c) => Err(self.error(format!("expected a key, found '{}'", c))),
+            None => Err(self.error("expected a key, found end of input")),
+        }
+    }
+
+    fn array(&mut self) -> Result<Bson, ParseError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Bson::Array(items));
+                }
+                None => return Err(self.error("unclosed '['")),
+                _ => {}
+            }
+
+            items.push(self.value()?);
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                Some(c) => return Err(self.error(format!("expected ',' or ']', found '{}'", c))),
+                None => return Err(self.error("unclosed '['")),
+            }
+        }
+    }
+
+    fn string(&mut self, quote: char) -> Result<String, ParseError> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut value = String::new();
+
+        loop {
+            match self.next() {
+                Some(c) if c == quote => return Ok(value),
+                Some('\\') => value.push(self.escape()?),
+                Some(c) => value.push(c),
+                None => return Err(self.error_at(start, "unterminated string")),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, ParseError> {
+        let c = match self.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('0') => '\0',
+            Some('u') => return self.unicode_escape(),
+            Some(c) => c,
+            None => return Err(self.error("unterminated string")),
+        };
+        Ok(c)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, ParseError> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("invalid unicode escape"));
+        }
+
+        // surrogate pair
+        if self.next() != Some('\\') || self.next() != Some('u') {
+            return Err(self.error("unpaired surrogate in unicode escape"));
+        }
+        let low = self.hex4()?;
+        let code = 0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseError> {
+        let start = self.pos;
+        let digits: String = (0..4).filter_map(|_| self.next()).collect();
+        u32::from_str_radix(&digits, 16).map_err(|_| self.error_at(start, "invalid unicode escape"))
+    }
+
+    fn regex(&mut self) -> Result<Bson, ParseError> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut pattern = String::new();
+        let mut in_class = false;
+
+        loop {
+            match self.next() {
+                Some('/') if !in_class => break,
+                Some('\\') => {
+                    pattern.push('\\');
+                    match self.next() {
+                        Some(c) => pattern.push(c),
+                        None => return Err(self.error_at(start, "unterminated regular expression")),
+                    }
+                }
+                Some('\n') | None => {
+                    return Err(self.error_at(start, "unterminated regular expression"));
+                }
+                Some(c) => {
+                    match c {
+                        '[' => in_class = true,
+                        ']' => in_class = false,
+                        _ => {}
+                    }
+                    pattern.push(c);
+                }
+            }
+        }
+
+        let mut options = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_alphabetic()) {
+            if !"imsxlu".contains(c) {
+                return Err(self.error(format!("unknown regular expression flag '{}'", c)));
+            }
+            options.push(c);
+            self.pos += 1;
+        }
+
+        Ok(regex(pattern, &options))
+    }
+
+    fn number(&mut self) -> Result<Bson, ParseError> {
+        let start = self.pos;
+        if matches!(self.peek(), Some('-' | '+')) {
+            self.pos += 1;
+        }
+
+        // signed Infinity/NaN
+        if self.peek().is_some_and(is_identifier_start) {
+            let sign = self.chars[start];
+            return match self.identifier().as_str() {
+                "Infinity" if sign == '-' => Ok(Bson::Double(f64::NEG_INFINITY)),
+                "Infinity" => Ok(Bson::Double(f64::INFINITY)),
+                other => Err(self.error_at(start, format!("unexpected '{}{}'", sign, other))),
+            };
+        }
+
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E'))
+        {
+            self.pos += 1;
+            // exponent sign
+            if matches!(self.chars[self.pos - 1], 'e' | 'E')
+                && matches!(self.peek(), Some('-' | '+'))
+            {
+                self.pos += 1;
+            }
+        }
+
+        let text: String = self.chars[start..self.pos].iter().collect();
+        let text = text.trim_start_matches('+');
+        let is_float = text.contains(['.', 'e', 'E']);
+
+        if !is_float && let Ok(i) = text.parse::<i64>() {
+            return Ok(match i32::try_from(i) {
+                Ok(i) => Bson::Int32(i),
+                Err(_) => Bson::Int64(i),
+            });
+        }
+        text.parse::<f64>()
+            .map(Bson::Double)
+            .map_err(|_| self.error_at(start, format!("invalid number '{}'", text)))
+    }
+
+    fn identifier(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(is_identifier_char) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    // keywords and constructors such as `true`, `ObjectId("...")` or
+    // `new Date()`
+    fn identifier_value(&mut self) -> Result<Bson, ParseError> {
+        let start = self.pos;
+        let mut name = self.identifier();
+        if name == "new" {
+            self.skip_whitespace();
+            name = self.identifier();
+        }
+
+        self.skip_whitespace();
+        let args = if self.peek() == Some('(') {
+            Some(self.arguments()?)
+        } else {
+            None
+        };
+
+        let value = match (name.as_str(), args) {
+            ("true", None) => Ok(Bson::Boolean(true)),
+            ("false", None) => Ok(Bson::Boolean(false)),
+            ("null", None) => Ok(Bson::Null),
+            ("undefined", None) => Ok(Bson::Undefined),
+            ("Infinity", None) => Ok(Bson::Double(f64::INFINITY)),
+            ("NaN", None) => Ok(Bson::Double(f64::NAN)),
+            ("MinKey", _) => Ok(Bson::MinKey),
+            ("MaxKey", _) => Ok(Bson::MaxKey),
+            (_, None) => Err(format!("unknown value '{}'", name)),
+            (name, Some(args)) => constructor(name, args),
+        };
+        value.map_err(|e| self.error_at(start, e))
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Bson>, ParseError> {
+        self.expect('(')?;
+        let mut args = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(')') => {
+                    self.pos += 1;
+                    return Ok(args);
+                }
+                None => return Err(self.error("unclosed '('")),
+                _ => {}
+            }
+
+            args.push(self.value()?);
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(')') => {}
+                Some(c) => return Err(self.error(format!("expected ',' or ')', found '{}'", c))),
+                None => return Err(self.error("unclosed '('")),
+            }
+        }
+    }
+}
+
+// builds the value of a mongosh constructor call
+fn constructor(name: &str, args: Vec<Bson>) -> Result<Bson, String> {
+    let mut args = args.into_iter();
+    let first = args.next();
+    let second = args.next();
+
+    match name {
+        "ObjectId" => match first {
+            None => Ok(Bson::ObjectId(ObjectId::new())),
+            Some(Bson::String(hex)) => ObjectId::parse_str(&hex)
+                .map(Bson::ObjectId)
+                .map_err(|_| format!("invalid ObjectId '{}'", hex)),
+            Some(_) => Err("ObjectId() takes a hex string".to_string()),
+        },
+        "ISODate" | "Date" => match first {
+            None => Ok(Bson::DateTime(DateTime::now())),
+            Some(Bson::String(date)) => parse_date(&date).map(Bson::DateTime),
+            Some(value) => match as_i64(&value) {
+                Some(millis) => Ok(Bson::DateTime(DateTime::from_millis(millis))),
+                None => Err(format!("{}() takes a date string or milliseconds", name)),
+            },
+        },
+        "NumberLong" | "Long" => {
+            let value = number_text(first, name)?;
+            from_extjson(json!({ "$numberLong": value }))
+        }
+        "NumberInt" | "Int32" => {
+            let value = number_text(first, name)?;
+            from_extjson(json!({ "$numberInt": value }))
+        }
+        "NumberDecimal" | "Decimal128" => {
+            let value = number_text(first, name)?;
+            from_extjson(json!({ "$numberDecimal": value }))
+        }
+        "Double" => {
+            let value = number_text(first, name)?;
+            value
+                .parse::<f64>()
+                .map(Bson::Double)
+                .map_err(|_| format!("invalid number '{}'", value))
+        }
+        "Timestamp" => {
+            let (t, i) = match (first, second) {
+                (Some(Bson::Document(doc)), None) => {
+                    (doc.get("t").and_then(as_i64), doc.get("i").and_then(as_i64))
+                }
+                (Some(t), Some(i)) => (as_i64(&t), as_i64(&i)),
+                _ => (None, None),
+            };
+            match (t, i) {
+                (Some(t), Some(i)) => from_extjson(json!({ "$timestamp": { "t": t, "i": i } })),
+                _ => Err("Timestamp() takes a time and an increment".to_string()),
+            }
+        }
+        "BinData" => match (first.as_ref().and_then(as_i64), second) {
+            (Some(subtype), Some(Bson::String(base64))) => from_extjson(json!({
+                "$binary": { "base64": base64, "subType": format!("{:02x}", subtype) }
+            })),
+            _ => Err("BinData() takes a subtype and a base64 string".to_string()),
+        },
+        "UUID" => match first {
+            Some(Bson::String(uuid)) => from_extjson(json!({ "$uuid": uuid })),
+            _ => Err("UUID() takes a string".to_string()),
+        },
+        "RegExp" => match (first, second) {
+            (Some(Bson::String(pattern)), None) => Ok(regex(pattern, "")),
+            (Some(Bson::String(pattern)), Some(Bson::String(options))) => {
+                Ok(regex(pattern, &options))
+            }
+            _ => Err("RegExp() takes a pattern and optional flags".to_string()),
+        },
+        _ => Err(format!("unknown function '{}'", name)),
+    }
+}
+
+// the server expects regex options in alphabetical order
+fn regex(pattern: String, options: &str) -> Bson {
+    let mut options: Vec<char> = options.chars().collect();
+    options.sort_unstable();
+    Bson::RegularExpression(Regex {
+        pattern,
+        options: options.into_iter().collect(),
+    })
+}
+
+fn from_extjson(value: serde_json::Value) -> Result<Bson, String> {
+    Bson::try_from(value).map_err(|e| format!("invalid Extended JSON: {}", e))
+}
+
+// mongosh accepts dates like `2024-01-01` or `2024-01-01 10:30`, the driver
+// wants full RFC 3339
+fn parse_date(input: &str) -> Result<DateTime, String> {
+    let mut date = input.trim().replacen(' ', "T", 1);
+    if date.len() == 10 {
+        date.push_str("T00:00:00");
+    }
+
+    let time_start = date.find('T').map_or(date.len(), |i| i + 1);
+    let has_zone = date[time_start..].contains(['Z', 'z', '+', '-']);
+    let time_len = date[time_start..]
+        .find(['Z', 'z', '+', '-'])
+        .unwrap_or(date.len() - time_start);
+    // HH:MM without seconds
+    if time_len == 5 {
+        date.insert_str(time_start + 5, ":00");
+    }
+    if !has_zone {
+        date.push('Z');
+    }
+
+    DateTime::parse_rfc3339_str(&date).map_err(|_| format!("invalid date '{}'", input))
+}
+
+fn as_i64(value: &Bson) -> Option<i64> {
+    match value {
+        Bson::Int32(i) => Some(*i as i64),
+        Bson::Int64(i) => Some(*i),
+        Bson::Double(f) if f.fract() == 0.0 => Some(*f as i64),
+        _ => None,
+    }
+}
+
+// number constructors take either a number or its text
+fn number_text(value: Option<Bson>, name: &str) -> Result<String, String> {
+    match value {
+        Some(Bson::String(text)) => Ok(text),
+        Some(Bson::Int32(i)) => Ok(i.to_string()),
+        Some(Bson::Int64(i)) => Ok(i.to_string()),
+        Some(Bson::Double(f)) => Ok(f.to_string()),
+        _ => Err(format!("{}() takes a number or a string", name)),
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '$'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{Binary, Decimal128, Timestamp, doc, spec::BinarySubtype};
+
+    use super::*;
+    use crate::syntax::{DisplayMode, to_pretty_string};
+
+    fn parse(input: &str) -> Bson {
+        parse_value(input).unwrap_or_else(|e| panic!("{}: {}", input, e))
+    }
+
+    fn error(input: &str) -> (usize, usize) {
+        let e = parse_value(input).expect_err(input);
+        (e.line, e.column)
+    }
+
+    #[test]
+    fn object_id() {
+        let hex = "65a1b2c3d4e5f60718293a4b";
+        let expected = Bson::ObjectId(ObjectId::parse_str(hex).unwrap());
+        assert_eq!(parse(&format!("ObjectId(\"{}\")", hex)), expected);
+        assert_eq!(parse(&format!("ObjectId('{}')", hex)), expected);
+        assert_eq!(parse(&format!("{{\"$oid\": \"{}\"}}", hex)), expected);
+        assert!(matches!(parse("ObjectId()"), Bson::ObjectId(_)));
+        assert!(parse_value("ObjectId(\"nothex\")").is_err());
+    }
+
+    #[test]
+    fn dates() {
+        let expected = Bson::DateTime(DateTime::from_millis(1_704_067_200_000));
+        assert_eq!(parse("ISODate(\"2024-01-01T00:00:00Z\")"), expected);
+        assert_eq!(parse("ISODate(\"2024-01-01\")"), expected);
+        assert_eq!(parse("ISODate(\"2024-01-01 00:00\")"), expected);
+        assert_eq!(parse("ISODate(\"2024-01-01T01:00:00+01:00\")"), expected);
+        assert_eq!(parse("new Date(1704067200000)"), expected);
+        assert_eq!(parse("{$date: \"2024-01-01T00:00:00Z\"}"), expected);
+        assert_eq!(parse("{$date: {$numberLong: \"1704067200000\"}}"), expected);
+        assert!(parse_value("ISODate(\"yesterday\")").is_err());
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse("42"), Bson::Int32(42));
+        assert_eq!(parse("5000000000"), Bson::Int64(5_000_000_000));
+        assert_eq!(parse("-1.5"), Bson::Double(-1.5));
+        assert_eq!(parse("NumberLong(7)"), Bson::Int64(7));
+        assert_eq!(
+            parse("NumberLong(\"9007199254740993\")"),
+            Bson::Int64(9_007_199_254_740_993)
+        );
+        assert_eq!(parse("{$numberLong: \"7\"}"), Bson::Int64(7));
+        assert_eq!(parse("NumberInt(7)"), Bson::Int32(7));
+        assert_eq!(parse("{$numberInt: \"7\"}"), Bson::Int32(7));
+        let decimal = Bson::Decimal128("0.10".parse::<Decimal128>().unwrap());
+        assert_eq!(parse("NumberDecimal(\"0.10\")"), decimal);
+        assert_eq!(parse("{$numberDecimal: \"0.10\"}"), decimal);
+    }
+
+    #[test]
+    fn uuid_and_binary() {
+        let uuid = Bson::Binary(Binary {
+            subtype: BinarySubtype::Uuid,
+            bytes: (0..16).collect(),
+        });
+        let text = "00010203-0405-0607-0809-0a0b0c0d0e0f";
+        assert_eq!(parse(&format!("UUID(\"{}\")", text)), uuid);
+        assert_eq!(parse(&format!("{{$uuid: \"{}\"}}", text)), uuid);
+
+        let binary = Bson::Binary(Binary {
+            subtype: BinarySubtype::Generic,
+            bytes: vec![1, 2, 3],
+        });
+        assert_eq!(parse("BinData(0, \"AQID\")"), binary);
+        assert_eq!(
+            parse("{$binary: {base64: \"AQID\", subType: \"00\"}}"),
+            binary
+        );
+    }
+
+    #[test]
+    fn timestamps() {
+        let expected = Bson::Timestamp(Timestamp {
+            time: 1_700_000_000,
+            increment: 3,
+        });
+        assert_eq!(parse("Timestamp(1700000000, 3)"), expected);
+        assert_eq!(parse("Timestamp({t: 1700000000, i: 3})"), expected);
+        assert_eq!(parse("{$timestamp: {t: 1700000000, i: 3}}"), expected);
+    }
+
+    #[test]
+    fn regexes() {
+        let expected = Bson::RegularExpression(Regex {
+            pattern: "^a[/]b".to_string(),
+            options: "im".to_string(),
+        });
+        assert_eq!(parse("/^a[/]b/mi"), expected);
+        assert_eq!(parse("RegExp(\"^a[/]b\", \"mi\")"), expected);
+        assert_eq!(
+            parse("{$regularExpression: {pattern: \"^a[/]b\", options: \"im\"}}"),
+            expected
+        );
+        assert_eq!(
+            parse("/a\\/b/"),
+            Bson::RegularExpression(Regex {
+                pattern: "a\\/b".to_string(),
+                options: String::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn nested_extended_json_in_shell_syntax() {
+        let parsed = parse_document(
+            "{at: {$gte: {$date: {$numberLong: \"0\"}}}, n: {$in: [NumberLong(1), {$numberLong: \"2\"}]}}",
+        )
+        .unwrap();
+        assert_eq!(
+            parsed,
+            doc! {
+                "at": { "$gte": DateTime::from_millis(0) },
+                "n": { "$in": [1_i64, 2_i64] },
+            }
+        );
+    }
+
+    #[test]
+    fn keeps_key_order() {
+        let parsed = parse_document("{b: 1, a: 2, c: 3}").unwrap();
+        let keys: Vec<&String> = parsed.keys().collect();
+        assert_eq!(keys, ["b", "a", "c"]);
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(error("{a: 1,\n  b: }"), (2, 6));
+        assert_eq!(error("{a: 1 b: 2}"), (1, 7));
+        assert_eq!(error("[1, 2"), (1, 6));
+        assert_eq!(error("{\n\n  when: Foo(1)\n}"), (3, 9));
+        assert_eq!(error("{a: ObjectId(\"xyz\")}"), (1, 5));
+
+        let e = parse_document("[1]").unwrap_err();
+        assert_eq!((e.line, e.column), (1, 1));
+        assert_eq!(
+            e.to_string(),
+            "line 1, column 1: expected a document starting with '{'"
+        );
+    }
+
+    #[test]
+    fn printed_documents_parse_back() {
+        let original = doc! {
+            "_id": ObjectId::parse_str("65a1b2c3d4e5f60718293a4b").unwrap(),
+            "at": DateTime::from_millis(1_704_067_200_123),
+            "before_1970": DateTime::from_millis(-86_400_000),
+            "int": 1_i32,
+            "long": 5_000_000_000_i64,
+            "double": 2.0,
+            "fraction": 0.25,
+            "nan": f64::NAN,
+            "infinity": f64::NEG_INFINITY,
+            "decimal": "1.50".parse::<Decimal128>().unwrap(),
+            "text": "quote \" and\nnewline",
+            "flag": true,
+            "missing": null,
+            "regex": Regex { pattern: "^a\\.b".to_string(), options: "i".to_string() },
+            "ts": Timestamp { time: 1, increment: 2 },
+            "bin": Binary { subtype: BinarySubtype::Generic, bytes: vec![0, 255] },
+            "uuid": Binary { subtype: BinarySubtype::Uuid, bytes: vec![7; 16] },
+            "min": Bson::MinKey,
+            "max": Bson::MaxKey,
+            "nested": { "list": [1_i32, "two", { "three": 3_i32 }] },
+            "empty": {},
+            "none": [],
+        };
+
+        for mode in [
+            DisplayMode::Relaxed,
+            DisplayMode::Canonical,
+            DisplayMode::Shell,
+        ] {
+            let text = to_pretty_string(&original, mode);
+            let parsed =
+                parse_document(&text).unwrap_or_else(|e| panic!("{:?}: {}\n{}", mode, e, text));
+            // NaN is never equal to itself, so compare the printed forms
+            assert_eq!(
+                to_pretty_string(&parsed, DisplayMode::Canonical),
+                to_pretty_string(&original, DisplayMode::Canonical),
+                "{:?}",
+                mode
+            );
+        }
+    }
+
+    #[test]
+    fn large_and_small_doubles_stay_doubles() {
+        let original = doc! {
+            "whole": 5e15,
+            "limit": 1e16,
+            "large": 1e20,
+            "above_long": 12_345_678_901_234_567_890.0,
+            "negative": -3e300,
+            "tiny": 1e-7,
+        };
+        for mode in [DisplayMode::Relaxed, DisplayMode::Shell] {
+            let text = to_pretty_string(&original, mode);
+            assert_eq!(
+                parse_document(&text).unwrap(),
+                original,
+                "{:?}\n{}",
+                mode,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn printing_loses_only_documented_details() {
+        let long = doc! { "n": 5_i64 };
+        let relaxed = to_pretty_string(&long, DisplayMode::Relaxed);
+        assert_eq!(parse_document(&relaxed).unwrap(), doc! { "n": 5_i32 });
+        let canonical = to_pretty_string(&long, DisplayMode::Canonical);
+        assert_eq!(parse_document(&canonical).unwrap(), long);
+
+        let slash = doc! { "r": Regex { pattern: "a/b".to_string(), options: String::new() } };
+        let shell = to_pretty_string(&slash, DisplayMode::Shell);
+        assert_eq!(
+            parse_document(&shell).unwrap(),
+            doc! { "r": Regex { pattern: "a\\/b".to_string(), options: String::new() } }
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use mongodb::bson::{Bson, Document, spec::BinarySubtype};
+
+const INDENT: &str = "  ";
+
+/// Notation documents are shown in. Output of every mode can be pasted back
+/// into a filter, but only Canonical parses to exactly the same BSON:
+/// Relaxed writes an Int64 as a plain number, which reads back as an Int32
+/// when it fits, and Shell escapes `/` in a regex pattern, which reads back
+/// as `\/` (matching the same strings). Doubles always keep a decimal point
+/// or an exponent, so they stay doubles in every mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayMode {
+    /// Extended JSON v2, relaxed: plain numbers and ISO dates where possible.
+    Relaxed,
+    /// Extended JSON v2, canonical: every non-string value keeps its type.
+    Canonical,
+    /// mongosh helpers such as `ObjectId("...")` and `ISODate("...")`.
+    Shell,
+}
+
+impl DisplayMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            DisplayMode::Relaxed => "Relaxed EJSON",
+            DisplayMode::Canonical => "Canonical EJSON",
+            DisplayMode::Shell => "mongosh",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            DisplayMode::Relaxed => DisplayMode::Canonical,
+            DisplayMode::Canonical => DisplayMode::Shell,
+            DisplayMode::Shell => DisplayMode::Relaxed,
+        }
+    }
+}
+
+/// Formats `doc` over multiple lines, keeping key order.
+pub fn to_pretty_string(doc: &Document, mode: DisplayMode) -> String {
+    let mut out = String::new();
+    write_document(&mut out, doc, mode, Some(0));
+    out
+}
+
+/// Formats `value` on a single line, keeping key order.
+pub fn to_compact_string(value: &Bson, mode: DisplayMode) -> String {
+    let mut out = String::new();
+    write_value(&mut out, value, mode, None);
+    out
+}
+
+/// Short description of a document or array, e.g. `{3 fields}` or
+/// `[12 items]`; `None` for other values.
+pub fn summary(value: &Bson) -> Option<String> {
+    match value {
+        Bson::Document(doc) if doc.len() == 1 => Some("{1 field}".to_string()),
+        Bson::Document(doc) => Some(format!("{{{} fields}}", doc.len())),
+        Bson::Array(items) if items.len() == 1 => Some("[1 item]".to_string()),
+        Bson::Array(items) => Some(format!("[{} items]", items.len())),
+        _ => None,
+    }
+}
+
+// `indent` is the current nesting depth, or None for single-line output
+fn write_value(out: &mut String, value: &Bson, mode: DisplayMode, indent: Option<usize>) {
+    match value {
+        Bson::Document(doc) => write_document(out, doc, mode, indent),
+        Bson::Array(items) => write_array(out, items, mode, indent),
+        _ => match mode {
+            DisplayMode::Shell => out.push_str(&shell_scalar(value)),
+            DisplayMode::Relaxed => write_json(out, &value.clone().into_relaxed_extjson()),
+            DisplayMode::Canonical => write_json(out, &value.clone().into_canonical_extjson()),
+        },
+    }
+}
+
+fn write_document(out: &mut String, doc: &Document, mode: DisplayMode, indent: Option<usize>) {
+    if doc.is_empty() {
+        out.push_str("{}");
+        return;
+    }
+
+    out.push('{');
+    for (i, (key, value)) in doc.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        match indent {
+            Some(depth) => newline(out, depth + 1),
+            None if i > 0 => out.push(' '),
+            None => {}
+        }
+        write_key(out, key, mode);
+        out.push_str(": ");
+        write_value(out, value, mode, indent.map(|depth| depth + 1));
+    }
+    if let Some(depth) = indent {
+        newline(out, depth);
+    }
+    out.push('}');
+}
+
+fn write_array(out: &mut String, items: &[Bson], mode: DisplayMode, indent: Option<usize>) {
+    if items.is_empty() {
+        out.push_str("[]");
+        return;
+    }
+
+    out.push('[');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        match indent {
+            Some(depth) => newline(out, depth + 1),
+            None if i > 0 => out.push(' '),
+            None => {}
+        }
+        write_value(out, item, mode, indent.map(|depth| depth + 1));
+    }
+    if let Some(depth) = indent {
+        newline(out, depth);
+    }
+    out.push(']');
+}
+
+// Extended JSON wrappers like `{"$oid": "..."}`, spaced like the rest of
+// the output
+fn write_json(out: &mut String, value: &serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            out.push('{');
+            for (i, (key, value)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                out.push_str(&quote(key));
+                out.push_str(": ");
+                write_json(out, value);
+            }
+            out.push('}');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+fn newline(out: &mut String, depth: usize) {
+    out.push('\n');
+    out.push_str(&INDENT.repeat(depth));
+}
+
+// mongosh leaves identifier-like keys unquoted
+fn write_key(out: &mut String, key: &str, mode: DisplayMode) {
+    let bare = mode == DisplayMode::Shell
+        && key
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '$');
+
+    if bare {
+        out.push_str(key);
+    } else {
+        out.push_str(&quote(key));
+    }
+}
+
+fn quote(s: &str) -> String {
+    serde_json::Value::String(s.to_string()).to_string()
+}
+
+fn shell_scalar(value: &Bson) -> String {
+    match value {
+        Bson::ObjectId(oid) => format!("ObjectId({})", quote(&oid.to_hex())),
+        Bson::DateTime(date) => match date.try_to_rfc3339_string() {
+            Ok(iso) => format!("ISODate({})", quote(&iso)),
+            Err(_) => format!("new Date({})", date.timestamp_millis()),
+        },
+        Bson::Int64(i) => format!("NumberLong({})", i),
+        Bson::Int32(i) => i.to_string(),
+        Bson::Double(f) if f.is_nan() => "NaN".to_string(),
+        Bson::Double(f) if f.is_infinite() => {
+            if *f > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+        }
+        // `{:?}` always writes a decimal point or an exponent (`1.0`,
+        // `1e16`), so the value reads back as a double
+        Bson::Double(f) => format!("{:?}", f),
+        Bson::Decimal128(d) => format!("NumberDecimal({})", quote(&d.to_string())),
+        Bson::String(s) => quote(s),
+        Bson::Boolean(b) => b.to_string(),
+        Bson::Null => "null".to_string(),
+        Bson::Undefined => "undefined".to_string(),
+        Bson::RegularExpression(regex) => {
+            format!("/{}/{}", escape_slashes(&regex.pattern), regex.options)
+        }
+        Bson::Timestamp(ts) => format!("Timestamp({{ t: {}, i: {} }})", ts.time, ts.increment),
+        Bson::Binary(binary) if binary.subtype == BinarySubtype::Uuid => match binary.to_uuid() {
+            Ok(uuid) => format!("UUID({})", quote(&uuid.to_string())),
+            Err(_) => bin_data(value),
+        },
+        Bson::Binary(_) => bin_data(value),
+        Bson::MinKey => "MinKey()".to_string(),
+        Bson::MaxKey => "MaxKey()".to_string(),
+        // no mongosh helper the parser understands, fall back to Extended JSON
+        other => other.clone().into_canonical_extjson().to_string(),
+    }
+}
+
+// escapes `/` in a regex pattern unless it already is
+fn escape_slashes(pattern: &str) -> String {
+    let mut out = String::new();
+    let mut escaped = false;
+    for c in pattern.chars() {
+        if c == '/' && !escaped {
+            out.push('\\');
+        }
+        escaped = c == '\\' && !escaped;
+        out.push(c);
+    }
+    out
+}
+
+fn bin_data(value: &Bson) -> String {
+    let Bson::Binary(binary) = value else {
+        return String::new();
+    };
+    let subtype: u8 = binary.subtype.into();
+    // the canonical form carries the base64 text the helper needs
+    let base64 = value.clone().into_canonical_extjson()["$binary"]["base64"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    format!("BinData({}, {})", subtype, quote(&base64))
+}
@@ -0,0 +1,215 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+};
+
+use super::{editor, header_line, highlight, status_text};
+use crate::{
+    app::{aggregation::AggregationFocus, state::AppState},
+    syntax,
+};
+
+pub fn render(f: &mut Frame, area: Rect, state: &AppState) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
+        .split(area);
+
+    let left_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(10), // stage list
+            Constraint::Length(3),
+        ])
+        .split(chunks[0]);
+
+    render_header(f, left_chunks[0], state);
+    render_editor(f, left_chunks[1], state);
+    match state.aggregation.focus {
+        AggregationFocus::SaveName | AggregationFocus::Saved => {
+            render_saved(f, left_chunks[2], state)
+        }
+        _ => render_stages(f, left_chunks[2], state),
+    }
+    render_footer(f, left_chunks[3], state);
+    render_preview(f, chunks[1], state);
+}
+
+fn render_header(f: &mut Frame, area: Rect, state: &AppState) {
+    let title = if let (Some(db), Some(coll)) = (&state.current_database, &state.current_collection)
+    {
+        format!("Aggregation: {}.{}", db, coll)
+    } else {
+        "Aggregation".to_string()
+    };
+
+    let header = Paragraph::new(header_line(title, state))
+        .style(
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        )
+        .block(Block::default().borders(Borders::ALL));
+
+    f.render_widget(header, area);
+}
+
+fn render_editor(f: &mut Frame, area: Rect, state: &AppState) {
+    let aggregation = &state.aggregation;
+    let focused = aggregation.focus == AggregationFocus::Editor;
+    let style = if focused {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default().fg(Color::White)
+    };
+
+    let title = match &aggregation.parse_error {
+        Some(e) => format!("Pipeline - {}", e),
+        None => "Pipeline (array of stages, mongosh syntax or Extended JSON)".to_string(),
+    };
+    let block = Block::default().borders(Borders::ALL).title(title);
+
+    editor::render(f, area, &aggregation.editor, block, style, focused);
+}
+
+fn render_stages(f: &mut Frame, area: Rect, state: &AppState) {
+    let aggregation = &state.aggregation;
+    let focused = aggregation.focus == AggregationFocus::Stages;
+
+    let items: Vec<ListItem> = aggregation
+        .stages
+        .iter()
+        .enumerate()
+        .map(|(i, stage)| {
+            let marker = if stage.enabled { "[x]" } else { "[ ]" };
+            let mut content = format!("{} {}. {}", marker, i + 1, stage.operator);
+            if stage.is_write() {
+                content.push_str(" (skipped in preview)");
+            }
+
+            let style = if focused && i == aggregation.selected_stage {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+                    .bg(Color::DarkGray)
+            } else if stage.enabled {
+                Style::default().fg(Color::White)
+            } else {
+                Style::default().fg(Color::DarkGray)
+            };
+
+            ListItem::new(Line::from(Span::styled(content, style)))
+        })
+        .collect();
+
+    let border_style = if focused {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    };
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(border_style)
+            .title("Stages (Space: toggle, Enter: preview up to stage)"),
+    );
+
+    f.render_widget(list, area);
+}
+
+fn render_saved(f: &mut Frame, area: Rect, state: &AppState) {
+    let aggregation = &state.aggregation;
+
+    if aggregation.focus == AggregationFocus::SaveName {
+        let input = Paragraph::new(format!("{}█", aggregation.save_name))
+            .style(Style::default().fg(Color::Yellow))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Save pipeline as (Enter to save, Esc to cancel)"),
+            );
+        f.render_widget(input, area);
+        return;
+    }
+
+    let items: Vec<ListItem> = aggregation
+        .saved
+        .iter()
+        .enumerate()
+        .map(|(i, saved)| {
+            let style = if i == aggregation.selected_saved {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+                    .bg(Color::DarkGray)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            ListItem::new(Line::from(Span::styled(saved.name.clone(), style)))
+        })
+        .collect();
+
+    let title = if items.is_empty() {
+        "Saved pipelines - none yet (Esc to go back)"
+    } else {
+        "Saved pipelines (Enter: load, 'd': delete, Esc: back)"
+    };
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow))
+            .title(title),
+    );
+
+    f.render_widget(list, area);
+}
+
+fn render_preview(f: &mut Frame, area: Rect, state: &AppState) {
+    let aggregation = &state.aggregation;
+
+    let content = aggregation
+        .preview
+        .iter()
+        .map(|doc| syntax::to_pretty_string(doc, state.display_mode))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let lines: Vec<Line> = content
+        .lines()
+        .skip(aggregation.preview_scroll)
+        .map(|line| highlight::highlight_line(line, &state.config.theme))
+        .collect();
+
+    let stage = match aggregation.preview_stage {
+        Some(i) => format!("after stage {}", i + 1),
+        None => "of full pipeline".to_string(),
+    };
+    let title = format!(
+        "Preview {} ({} documents, PgUp/PgDn to scroll)",
+        stage,
+        aggregation.preview.len()
+    );
+
+    let paragraph = Paragraph::new(lines)
+        .style(Style::default().fg(Color::White))
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(paragraph, area);
+}
+
+fn render_footer(f: &mut Frame, area: Rect, state: &AppState) {
+    let footer_text =
+        "Ctrl+R: run | Tab: editor/stages | Ctrl+S: save | Ctrl+O: open saved | Esc: back";
+    let footer_text = status_text(state).unwrap_or_else(|| footer_text.to_string());
+    let footer = Paragraph::new(footer_text)
+        .style(Style::default().fg(Color::Gray))
+        .block(Block::default().borders(Borders::ALL));
+
+    f.render_widget(footer, area);
+}
@@ -6,6 +6,7 @@ use ratatui::{
     widgets::{Block, Borders, List, ListItem, Paragraph},
 };
 
+use super::{header_line, status_text};
 use crate::app::state::AppState;
 
 pub fn render(f: &mut Frame, area: Rect, state: &AppState) {
@@ -20,7 +21,7 @@ pub fn render(f: &mut Frame, area: Rect, state: &AppState) {
 
     render_header(f, chunks[0], state);
     render_collection_list(f, chunks[1], state);
-    render_footer(f, chunks[2]);
+    render_footer(f, chunks[2], state);
 }
 
 fn render_header(f: &mut Frame, area: Rect, state: &AppState) {
@@ -30,7 +31,7 @@ fn render_header(f: &mut Frame, area: Rect, state: &AppState) {
         "No database selected".to_string()
     };
 
-    f.render_widget(title, area);
+    f.render_widget(header_line(title, state), area);
 }
 
 fn render_collection_list(f: &mut Frame, area: Rect, state: &AppState) {
@@ -67,8 +68,9 @@ fn render_collection_list(f: &mut Frame, area: Rect, state: &AppState) {
     f.render_widget(list, area);
 }
 
-fn render_footer(f: &mut Frame, area: Rect) {
+fn render_footer(f: &mut Frame, area: Rect, state: &AppState) {
     let footer_text = "Press 'q' to quit | ↑/↓ to navigate | Enter to view documents | Backspace to go back | 'r' to refresh";
+    let footer_text = status_text(state).unwrap_or_else(|| footer_text.to_string());
     let footer = Paragraph::new(footer_text)
         .style(Style::default().fg(Color::Gray))
         .block(Block::default().borders(Borders::ALL));
@@ -6,14 +6,28 @@ use ratatui::{
     widgets::{Block, Borders, List, ListItem, Paragraph},
 };
 
-use crate::app::state::AppState;
+use super::status_text;
+use crate::{
+    app::{
+        connection_form::{ConnectionForm, FormField},
+        state::{AppState, ConnectionFocus},
+    },
+    credentials,
+};
 
 pub fn render(f: &mut Frame, area: Rect, state: &AppState) {
+    // the form sits above the URI it edits
+    let form_height = if state.connection_form.is_some() {
+        FormField::ALL.len() as u16 + 2
+    } else {
+        0
+    };
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Length(3),
             Constraint::Length(5),
+            Constraint::Length(form_height),
             Constraint::Length(3),
             Constraint::Min(0),
             Constraint::Length(3),
@@ -22,13 +36,115 @@ pub fn render(f: &mut Frame, area: Rect, state: &AppState) {
 
     render_title(f, chunks[0]);
     render_instructions(f, chunks[1]);
-    render_input(f, chunks[2], state);
+    if let Some(form) = &state.connection_form {
+        render_form(f, chunks[2], state, form);
+    }
+    render_input(f, chunks[3], state);
 
-    if state.show_history && !state.connection_history.is_empty() {
-        render_history(f, chunks[3], state);
+    match state.connection_focus {
+        ConnectionFocus::History => render_history(f, chunks[4], state),
+        _ => render_profiles(f, chunks[4], state),
     }
 
-    render_footer(f, chunks[4], state);
+    render_footer(f, chunks[5], state);
+}
+
+fn render_form(f: &mut Frame, area: Rect, state: &AppState, form: &ConnectionForm) {
+    let focused = state.connection_focus == ConnectionFocus::Input;
+    let lines: Vec<Line> = FormField::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let selected = focused && i == form.selected;
+            let label_style = if selected {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::Gray)
+            };
+            let value = form.display_value(*field);
+            let value = match (field.is_choice(), selected) {
+                (true, _) => format!("◀ {} ▶", value),
+                (false, true) => format!("{}_", value),
+                (false, false) => value,
+            };
+            Line::from(vec![
+                Span::styled(format!("{:<22}", field.label()), label_style),
+                Span::styled(value, Style::default().fg(Color::White)),
+            ])
+        })
+        .collect();
+
+    let border_style = if focused {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default().fg(Color::White)
+    };
+    let form = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(border_style)
+            .title("Connection settings"),
+    );
+    f.render_widget(form, area);
+}
+
+fn render_profiles(f: &mut Frame, area: Rect, state: &AppState) {
+    let focused = state.connection_focus == ConnectionFocus::Profiles;
+    let items: Vec<ListItem> = state
+        .profiles
+        .iter()
+        .enumerate()
+        .map(|(i, profile)| {
+            let style = if focused && i == state.selected_profile_index {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+                    .bg(Color::DarkGray)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            let name_style = match profile.color() {
+                Some(color) => style.fg(color),
+                None => style,
+            };
+            let mut spans = vec![
+                Span::styled(format!("{:<20}", profile.name), name_style),
+                Span::styled(credentials::mask_password(&profile.uri), style),
+            ];
+            if let Some(db) = &profile.default_database {
+                spans.push(Span::styled(format!("  [{}]", db), style));
+            }
+            if profile.read_only {
+                spans.push(Span::styled("  read-only", style.fg(Color::Red)));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    let title = if state.profiles.is_empty() {
+        "Profiles - none yet (Ctrl+S saves the URI above as one)"
+    } else if focused {
+        "Profiles (↑/↓ to select, Enter to connect)"
+    } else {
+        "Profiles (Tab to select)"
+    };
+    let border_style = if focused {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default().fg(Color::White)
+    };
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(border_style)
+                .title(title),
+        )
+        .style(Style::default().fg(Color::White));
+
+    f.render_widget(list, area);
 }
 
 fn render_history(f: &mut Frame, area: Rect, state: &AppState) {
@@ -45,7 +161,10 @@ fn render_history(f: &mut Frame, area: Rect, state: &AppState) {
             } else {
                 Style::default().fg(Color::White)
             };
-            ListItem::new(Line::from(Span::styled(uri.clone(), style)))
+            ListItem::new(Line::from(Span::styled(
+                credentials::mask_password(uri),
+                style,
+            )))
         })
         .collect();
 
@@ -74,7 +193,7 @@ fn render_title(f: &mut Frame, area: Rect) {
 }
 
 fn render_instructions(f: &mut Frame, area: Rect) {
-    let text = "Enter MongoDB URI and press Enter to connect\nExamples: mongodb://localhost:27017 or mongodb+srv://...";
+    let text = "Enter MongoDB URI and press Enter to connect, or Ctrl+F to fill in a form\nExamples: mongodb://localhost:27017 or mongodb+srv://...";
     let instructions = Paragraph::new(text)
         .style(Style::default().fg(Color::Gray))
         .block(Block::default().borders(Borders::ALL).title("Instructions"));
@@ -82,12 +201,15 @@ fn render_instructions(f: &mut Frame, area: Rect) {
 }
 
 fn render_input(f: &mut Frame, area: Rect, state: &AppState) {
-    let input_style = if state.input_mode {
+    // while the form is open the URI follows it rather than being typed
+    let typing =
+        state.connection_focus == ConnectionFocus::Input && state.connection_form.is_none();
+    let input_style = if typing {
         Style::default().fg(Color::Yellow)
     } else {
         Style::default().fg(Color::White)
     };
-    let input = Paragraph::new(state.connection_input.as_str())
+    let input = Paragraph::new(credentials::mask_password(&state.connection_input))
         .style(input_style)
         .block(
             Block::default()
@@ -98,18 +220,29 @@ fn render_input(f: &mut Frame, area: Rect, state: &AppState) {
 }
 
 fn render_footer(f: &mut Frame, area: Rect, state: &AppState) {
-    let text = if state.error.is_some() {
-        format!(
-            "Error: {} | Press Esc to clear",
-            state.error.as_ref().unwrap()
-        )
-    } else if state.loading {
-        "Connecting...".to_string()
-    } else if state.show_history {
-        "↑/↓: Navigate | Enter: Select | Tab: Hide history | Ctrl+C: Quit".to_string()
+    let text = if let Some(error) = &state.error {
+        format!("Error: {} | Press Esc to clear", error)
+    } else if let Some(status) = status_text(state) {
+        status
     } else {
-        "Enter: Connect | Ctrl+V: Paste | Tab: Show history | Esc: Clear | Ctrl+C/Ctrl+Q: Quit"
-            .to_string()
+        match state.connection_focus {
+            ConnectionFocus::Input if state.connection_form.is_some() => {
+                "↑/↓: Field | ←/→/Space: Change | Enter: Check and connect | \
+                 Ctrl+S: Save as profile | Tab: Profiles | Esc/Ctrl+F: Back to URI"
+            }
+            ConnectionFocus::Input => {
+                "Enter: Connect | Ctrl+V: Paste | Ctrl+F: Form | Ctrl+S: Save as profile | \
+                 Tab: Profiles | Esc: Clear | Ctrl+C/Ctrl+Q: Quit"
+            }
+            ConnectionFocus::Profiles => {
+                "↑/↓: Navigate | Enter: Connect | n: New | e: Edit | d: Delete | Tab: History | \
+                 Esc: Back | Ctrl+C: Quit"
+            }
+            ConnectionFocus::History => {
+                "↑/↓: Navigate | Enter: Select | Tab: Back to input | Ctrl+C: Quit"
+            }
+        }
+        .to_string()
     };
     let footer = Paragraph::new(text)
         .style(Style::default().fg(Color::Gray))
@@ -6,6 +6,7 @@ use ratatui::{
     widgets::{Block, Borders, List, ListItem, Paragraph},
 };
 
+use super::{header_line, status_text};
 use crate::app::state::AppState;
 
 pub fn render(f: &mut Frame, area: Rect, state: &AppState) {
@@ -20,7 +21,7 @@ pub fn render(f: &mut Frame, area: Rect, state: &AppState) {
 
     render_header(f, chunks[0], state);
     render_database_list(f, chunks[1], state);
-    render_footer(f, chunks[2]);
+    render_footer(f, chunks[2], state);
 }
 
 fn render_header(f: &mut Frame, area: Rect, state: &AppState) {
@@ -32,7 +33,7 @@ fn render_header(f: &mut Frame, area: Rect, state: &AppState) {
     } else {
         "Mongonaut - Not connected".to_string()
     };
-    let header = Paragraph::new(title)
+    let header = Paragraph::new(header_line(title, state))
         .style(
             Style::default()
                 .fg(Color::Cyan)
@@ -78,9 +79,10 @@ fn render_database_list(f: &mut Frame, area: Rect, state: &AppState) {
     f.render_widget(list, area);
 }
 
-fn render_footer(f: &mut Frame, area: Rect) {
-    let footer_text =
-        "Press 'q' to quit | ↑/↓ to navigate | Enter to select database | 'r' to refresh";
+fn render_footer(f: &mut Frame, area: Rect, state: &AppState) {
+    let footer_text = "Press 'q' to quit | ↑/↓ to navigate | Enter to select database | \
+                       'r' to refresh | Backspace to disconnect";
+    let footer_text = status_text(state).unwrap_or_else(|| footer_text.to_string());
     let footer = Paragraph::new(footer_text)
         .style(Style::default().fg(Color::DarkGray))
         .block(Block::default().borders(Borders::ALL));
@@ -3,12 +3,52 @@ use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+    widgets::{Block, Borders, Cell, List, ListItem, Paragraph, Row, Table, TableState, Wrap},
 };
 
-use crate::app::state::AppState;
+use mongodb::bson::Bson;
+
+use super::{editor, header_line, highlight, status_text};
+use crate::{
+    app::{
+        grid,
+        state::{AppState, ContentView, PagingMode},
+    },
+    syntax,
+};
 
 pub fn render(f: &mut Frame, area: Rect, state: &AppState) {
+    // one line per active filter/sort/projection when not editing, and the
+    // query editor grows with its content
+    let filter_height = if state.query_mode {
+        2 + state.query_editor().lines().len().clamp(1, 10) as u16
+    } else if state.filter_mode || state.page_input_mode {
+        3
+    } else {
+        2 + query_summary(state).len().max(1) as u16
+    };
+
+    if state.grid_view {
+        // the table needs the full width, the document goes below it
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Length(filter_height),
+                Constraint::Percentage(50),
+                Constraint::Min(0),
+                Constraint::Length(3),
+            ])
+            .split(area);
+
+        render_header(f, rows[0], state);
+        render_filter_input(f, rows[1], state);
+        render_document_grid(f, rows[2], state);
+        render_document_content(f, rows[3], state);
+        render_footer(f, rows[4], state);
+        return;
+    }
+
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
@@ -18,7 +58,7 @@ pub fn render(f: &mut Frame, area: Rect, state: &AppState) {
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Length(3),
-            Constraint::Length(3), // filter input
+            Constraint::Length(filter_height), // filter input
             Constraint::Min(0),
             Constraint::Length(3),
         ])
@@ -27,7 +67,7 @@ pub fn render(f: &mut Frame, area: Rect, state: &AppState) {
     render_header(f, left_chunks[0], state);
     render_filter_input(f, left_chunks[1], state);
     render_document_list(f, left_chunks[2], state);
-    render_footer(f, left_chunks[3]);
+    render_footer(f, left_chunks[3], state);
     render_document_content(f, chunks[1], state);
 }
 
@@ -39,7 +79,7 @@ fn render_header(f: &mut Frame, area: Rect, state: &AppState) {
         "No collection selected".to_string()
     };
 
-    let header = Paragraph::new(title)
+    let header = Paragraph::new(header_line(title, state))
         .style(
             Style::default()
                 .fg(Color::Cyan)
@@ -51,39 +91,80 @@ fn render_header(f: &mut Frame, area: Rect, state: &AppState) {
 }
 
 fn render_filter_input(f: &mut Frame, area: Rect, state: &AppState) {
-    let (style, title, text) = if state.query_mode {
+    if state.query_mode {
+        let title = format!(
+            "Advanced Query Mode: {} (mongosh syntax - Enter to apply, Alt+Enter for new line, Esc to cancel)",
+            state.query_target.label()
+        );
+        let block = Block::default().borders(Borders::ALL).title(title);
+        let style = Style::default().fg(Color::Magenta);
+        editor::render(f, area, state.query_editor(), block, style, true);
+        return;
+    }
+
+    let (style, title, lines) = if state.page_input_mode {
         (
-            Style::default().fg(Color::Magenta),
-            "Advanced Query Mode (JSON - Enter to apply, Esc to cancel)",
-            state.query_input.as_str(),
+            Style::default().fg(Color::Cyan),
+            "Go to page (Enter to jump, Esc to cancel)".to_string(),
+            vec![Line::from(state.page_input.as_str())],
         )
     } else if state.filter_mode {
         (
             Style::default().fg(Color::Yellow),
-            "Search Mode (type to filter, Esc to clear)",
-            state.filter_input.as_str(),
+            "Search Mode (type to filter, Esc to clear)".to_string(),
+            vec![Line::from(state.filter_input.as_str())],
         )
-    } else if state.filter.is_some() {
+    } else if state.filter.is_some() || state.sort.is_some() || state.projection.is_some() {
         (
             Style::default().fg(Color::Green),
-            "Active Filter (press 'f' to search, '/' for query, Esc to clear)",
-            "", // Just use empty string for now
+            "Active Query ('f' search, '/' filter, 's' sort, 'o' projection, Esc to clear filter)"
+                .to_string(),
+            query_summary(state),
         )
     } else {
         (
             Style::default().fg(Color::White),
-            "No filter (press 'f' to search, '/' for advanced query)",
-            "",
+            "No filter ('f' search, '/' filter, 's' sort, 'o' projection)".to_string(),
+            Vec::new(),
         )
     };
 
-    let filter_widget = Paragraph::new(text)
+    let filter_widget = Paragraph::new(lines)
         .style(style)
         .block(Block::default().borders(Borders::ALL).title(title));
 
     f.render_widget(filter_widget, area);
 }
 
+// the active filter, sort and projection in the display notation, one per
+// line
+fn query_summary(state: &AppState) -> Vec<Line<'static>> {
+    let parts = [
+        ("filter", &state.filter),
+        ("sort", &state.sort),
+        ("projection", &state.projection),
+    ];
+
+    parts
+        .into_iter()
+        .filter_map(|(label, doc)| {
+            let doc = doc.as_ref()?;
+            let json = syntax::to_compact_string(&Bson::Document(doc.clone()), state.display_mode);
+            let mut spans = vec![
+                Span::styled(format!("{}: ", label), Style::default().fg(Color::Gray)),
+                Span::raw(json),
+            ];
+            if label == "sort" && state.paging_mode == PagingMode::Stream {
+                spans.push(Span::styled(
+                    " (ignored while streaming)",
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            Some(Line::from(spans))
+        })
+        .collect()
+}
+
 fn render_document_list(f: &mut Frame, area: Rect, state: &AppState) {
     let items: Vec<ListItem> = state
         .documents
@@ -95,8 +176,9 @@ fn render_document_list(f: &mut Frame, area: Rect, state: &AppState) {
                 .map(|v| format!("{}", v))
                 .unwrap_or_else(|| format!("Doc {}", i + 1));
 
-            let content = if id.len() > 25 {
-                format!("{}...", &id[..22])
+            // by chars, as string ids may hold multi-byte characters
+            let content = if id.chars().count() > 25 {
+                format!("{}...", id.chars().take(22).collect::<String>())
             } else {
                 id
             };
@@ -114,25 +196,142 @@ fn render_document_list(f: &mut Frame, area: Rect, state: &AppState) {
         })
         .collect();
 
-    let title = format!(
-        "Documents ({}/{})",
-        state.documents.len(),
-        state.documents.len()
-    );
     let list = List::new(items)
-        .block(Block::default().borders(Borders::ALL).title(title))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(list_title(state)),
+        )
         .style(Style::default().fg(Color::White));
 
     f.render_widget(list, area);
 }
 
+// page or stream position and query timing
+fn list_title(state: &AppState) -> String {
+    let mut title = match state.paging_mode {
+        PagingMode::Pages => format!(
+            "Documents - page {} of {} ({} matching documents)",
+            state.current_page + 1,
+            state.page_count(),
+            state.total_count
+        ),
+        PagingMode::Stream => format!(
+            "Documents - streaming {}-{} of {}{}{}",
+            state.stream_offset + state.documents.len().min(1),
+            state.stream_offset + state.documents.len(),
+            state.total_count,
+            if state.stream_has_after { " ▼" } else { "" },
+            if state.stream_by_offset {
+                " (mixed _id types, using skip)"
+            } else {
+                ""
+            }
+        ),
+    };
+    if let Some(elapsed) = state.query_time {
+        title.push_str(&format!(" in {}ms", elapsed.as_millis()));
+    }
+    title
+}
+
+fn render_document_grid(f: &mut Frame, area: Rect, state: &AppState) {
+    let grid = &state.grid;
+    let mut title = list_title(state);
+    if grid.hidden_count() > 0 {
+        title.push_str(&format!(
+            " - {} hidden columns ('X' to show)",
+            grid.hidden_count()
+        ));
+    }
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let width = block.inner(area).width;
+
+    // scroll right until the selected column fits, one space between columns
+    let visible: Vec<_> = grid.visible_columns().collect();
+    let selected = visible
+        .iter()
+        .position(|(i, _)| *i == grid.selected_column)
+        .unwrap_or(0);
+    let mut first = visible
+        .iter()
+        .position(|(i, _)| *i >= grid.first_column)
+        .unwrap_or(0)
+        .min(selected);
+    let span = |from: usize| -> u16 {
+        visible[from..=selected]
+            .iter()
+            .map(|(_, column)| column.width + 1)
+            .sum()
+    };
+    while first < selected && span(first) > width {
+        first += 1;
+    }
+
+    let mut shown = Vec::new();
+    let mut used = 0;
+    for (index, column) in &visible[first.min(visible.len())..] {
+        if used >= width {
+            break;
+        }
+        shown.push((*index, *column));
+        used += column.width + 1;
+    }
+
+    let header = Row::new(shown.iter().map(|(index, column)| {
+        let style = if *index == grid.selected_column {
+            Style::default().fg(Color::Black).bg(Color::Cyan)
+        } else {
+            Style::default().fg(state.config.theme.key_color)
+        };
+        Cell::from(truncate(&column.name, column.width)).style(style.add_modifier(Modifier::BOLD))
+    }));
+
+    let rows = state.documents.iter().map(|doc| {
+        Row::new(shown.iter().map(|(_, column)| {
+            let text = grid::cell_text(doc.get(&column.name), state.display_mode);
+            Cell::from(truncate(&text, column.width))
+        }))
+    });
+
+    let widths: Vec<Constraint> = shown
+        .iter()
+        .map(|(_, column)| Constraint::Length(column.width))
+        .collect();
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(block)
+        .style(Style::default().fg(Color::White))
+        .row_highlight_style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD)
+                .bg(Color::DarkGray),
+        );
+
+    let mut table_state = TableState::default().with_selected(Some(state.selected_doc_index));
+    f.render_stateful_widget(table, area, &mut table_state);
+}
+
+// cuts `text` to `width` characters, marking the cut with an ellipsis
+fn truncate(text: &str, width: u16) -> String {
+    let width = width as usize;
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
 fn render_document_content(f: &mut Frame, area: Rect, state: &AppState) {
+    if state.content_view == ContentView::Tree {
+        render_document_tree(f, area, state);
+        return;
+    }
+
     let content = if let Some(doc) = state.get_selected_document() {
-        // pretty print JSON with indentation
-        match serde_json::to_string_pretty(&doc) {
-            Ok(json) => json,
-            Err(_) => format!("{:?}", doc),
-        }
+        syntax::to_pretty_string(doc, state.display_mode)
     } else {
         "No document selected".to_string()
     };
@@ -141,7 +340,7 @@ fn render_document_content(f: &mut Frame, area: Rect, state: &AppState) {
     let lines: Vec<Line> = content
         .lines()
         .skip(state.doc_scroll_offset)
-        .map(|line| Line::from(line.to_string()))
+        .map(|line| highlight::highlight_line(line, &state.config.theme))
         .collect();
 
     let paragraph = Paragraph::new(lines)
@@ -149,15 +348,90 @@ fn render_document_content(f: &mut Frame, area: Rect, state: &AppState) {
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .title("Document Content (PgUp/PgDn to scroll)"),
+                .border_style(content_border_style(state))
+                .title(format!(
+                    "Document Content - {} ('m' to switch, 't' for tree, PgUp/PgDn to scroll)",
+                    state.display_mode.label()
+                )),
         )
         .wrap(Wrap { trim: false });
 
     f.render_widget(paragraph, area);
 }
 
-fn render_footer(f: &mut Frame, area: Rect) {
-    let footer_text = "q: quit | ↑/↓: navigate | Backspace: back | PgUp/PgDn: scroll | 'f': filter | 'r': refresh";
+fn render_document_tree(f: &mut Frame, area: Rect, state: &AppState) {
+    let theme = &state.config.theme;
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(content_border_style(state))
+        .title("Document Tree (Tab: focus, Enter: expand/collapse, E/C: expand/collapse all, 't': text)");
+
+    let Some(doc) = state.get_selected_document() else {
+        let paragraph = Paragraph::new("No document selected").block(block);
+        f.render_widget(paragraph, area);
+        return;
+    };
+
+    let rows = state.tree.rows(doc);
+    let height = (block.inner(area).height as usize).max(1);
+    let top = state.tree.cursor.saturating_sub(height - 1);
+
+    let lines: Vec<Line> = rows
+        .iter()
+        .enumerate()
+        .skip(top)
+        .take(height)
+        .map(|(i, row)| {
+            let marker = match (row.is_container(), row.expanded) {
+                (true, true) => "▼ ",
+                (true, false) => "▶ ",
+                (false, _) => "  ",
+            };
+            let mut spans = vec![
+                Span::raw("  ".repeat(row.depth)),
+                Span::styled(marker, Style::default().fg(theme.secondary_color)),
+                Span::styled(row.key.clone(), Style::default().fg(theme.key_color)),
+                Span::styled(": ", Style::default().fg(theme.secondary_color)),
+            ];
+            match syntax::summary(row.value) {
+                Some(summary) => spans.push(Span::styled(
+                    summary,
+                    Style::default().fg(theme.secondary_color),
+                )),
+                None => {
+                    let value = syntax::to_compact_string(row.value, state.display_mode);
+                    spans.extend(highlight::highlight_line(&value, theme).spans);
+                }
+            }
+
+            let line = Line::from(spans);
+            if state.content_focus && i == state.tree.cursor {
+                line.style(
+                    Style::default()
+                        .bg(Color::DarkGray)
+                        .add_modifier(Modifier::BOLD),
+                )
+            } else {
+                line
+            }
+        })
+        .collect();
+
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+// the document pane's border shows whether it has keyboard focus
+fn content_border_style(state: &AppState) -> Style {
+    if state.content_focus {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    }
+}
+
+fn render_footer(f: &mut Frame, area: Rect, state: &AppState) {
+    let footer_text = "↑/↓: navigate | n/p: page | g/G: first/last | ':': go to page | +/-: page size | PgUp/PgDn: scroll | 'S': stream | 'f': search | '/': filter | 's': sort | 'o': projection | 'a': aggregate | 'm': display mode | 't': tree | 'T': table | 'i': insert | 'e': edit | 'c': copy | 'd'/'D': delete one/matching | 'U': update matching | 'u': undo | Tab: focus document | 'r': refresh | Backspace: back";
+    let footer_text = status_text(state).unwrap_or_else(|| footer_text.to_string());
     let footer = Paragraph::new(footer_text)
         .style(Style::default().fg(Color::Gray))
         .block(Block::default().borders(Borders::ALL));
@@ -0,0 +1,89 @@
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph},
+};
+
+use crate::app::editor::TextEditor;
+
+/// Draws `editor` inside `block`, scrolled so the cursor stays visible. The
+/// cursor and its matching bracket are only highlighted when `focused`.
+pub fn render(
+    f: &mut Frame,
+    area: Rect,
+    editor: &TextEditor,
+    block: Block,
+    style: Style,
+    focused: bool,
+) {
+    let inner = block.inner(area);
+    let height = (inner.height as usize).max(1);
+    let width = (inner.width as usize).max(1);
+
+    let (row, col) = editor.cursor();
+    let top = row.saturating_sub(height - 1);
+    let left = col.saturating_sub(width - 1);
+
+    let cursor_style = Style::default().add_modifier(Modifier::REVERSED);
+    let bracket_style = Style::default()
+        .fg(Color::Black)
+        .bg(Color::Cyan)
+        .add_modifier(Modifier::BOLD);
+
+    let mut marks = Vec::new();
+    if focused {
+        marks.push((row, col, cursor_style));
+        if let Some((r, c)) = editor.matching_bracket() {
+            marks.push((r, c, bracket_style));
+        }
+    }
+
+    let lines: Vec<Line> = editor
+        .lines()
+        .iter()
+        .enumerate()
+        .skip(top)
+        .take(height)
+        .map(|(i, line)| {
+            let line_marks: Vec<(usize, Style)> = marks
+                .iter()
+                .filter(|(r, _, _)| *r == i)
+                .map(|(_, c, style)| (*c, *style))
+                .collect();
+            styled_line(line, left, &line_marks)
+        })
+        .collect();
+
+    let paragraph = Paragraph::new(lines).style(style).block(block);
+    f.render_widget(paragraph, area);
+}
+
+// splits `line` into spans so marked characters get their own style; a mark
+// past the end of the line is drawn on a trailing space
+fn styled_line(line: &str, left: usize, marks: &[(usize, Style)]) -> Line<'static> {
+    let mut chars: Vec<char> = line.chars().collect();
+    if marks.iter().any(|(col, _)| *col >= chars.len()) {
+        chars.push(' ');
+    }
+
+    let mut spans = Vec::new();
+    let mut plain = String::new();
+    for (col, c) in chars.into_iter().enumerate().skip(left) {
+        match marks.iter().find(|(mark, _)| *mark == col) {
+            Some((_, style)) => {
+                if !plain.is_empty() {
+                    spans.push(Span::raw(std::mem::take(&mut plain)));
+                }
+                spans.push(Span::styled(c.to_string(), *style));
+            }
+            None => plain.push(c),
+        }
+    }
+    if !plain.is_empty() {
+        spans.push(Span::raw(plain));
+    }
+
+    Line::from(spans)
+}
@@ -0,0 +1,125 @@
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+
+use crate::{models::Theme, syntax::parser::EXTJSON_KEYS};
+
+/// Colors one line of a document printed by `syntax::to_pretty_string`, in
+/// any display mode. Strings never span lines there, so lines can be
+/// highlighted independently.
+pub fn highlight_line(line: &str, theme: &Theme) -> Line<'static> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut spans = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        let start = pos;
+
+        let color = if c == '"' || c == '\'' {
+            pos = skip_string(&chars, pos);
+            let text: String = chars[start..pos].iter().collect();
+            if is_followed_by_colon(&chars, pos) {
+                let key = text.trim_matches(['"', '\'']);
+                if EXTJSON_KEYS.contains(&key) {
+                    Some(theme.bson_type_color)
+                } else {
+                    Some(theme.key_color)
+                }
+            } else {
+                Some(theme.string_color)
+            }
+        } else if c.is_ascii_digit()
+            || (c == '-' && chars.get(pos + 1).is_some_and(|c| c.is_ascii_digit()))
+        {
+            pos += 1;
+            while pos < chars.len()
+                && (chars[pos].is_ascii_alphanumeric() || matches!(chars[pos], '.' | '-' | '+'))
+            {
+                pos += 1;
+            }
+            Some(theme.number_color)
+        } else if c.is_alphabetic() || c == '_' || c == '$' || c == '-' {
+            pos += 1;
+            while pos < chars.len()
+                && (chars[pos].is_alphanumeric() || chars[pos] == '_' || chars[pos] == '$')
+            {
+                pos += 1;
+            }
+            let word: String = chars[start..pos].iter().collect();
+            Some(word_color(&word, &chars, pos, theme))
+        } else if c == '/' {
+            // regex literal in mongosh mode
+            pos = skip_regex(&chars, pos);
+            Some(theme.bson_type_color)
+        } else if c.is_whitespace() {
+            while pos < chars.len() && chars[pos].is_whitespace() {
+                pos += 1;
+            }
+            None
+        } else {
+            pos += 1;
+            Some(theme.secondary_color)
+        };
+
+        let text: String = chars[start..pos].iter().collect();
+        spans.push(match color {
+            Some(color) => Span::styled(text, Style::default().fg(color)),
+            None => Span::raw(text),
+        });
+    }
+
+    Line::from(spans)
+}
+
+fn word_color(word: &str, chars: &[char], end: usize, theme: &Theme) -> Color {
+    match word.trim_start_matches('-') {
+        "true" | "false" => theme.boolean_color,
+        "null" | "undefined" => theme.null_color,
+        "NaN" | "Infinity" => theme.number_color,
+        _ if is_followed_by_colon(chars, end) => theme.key_color,
+        // helpers such as ObjectId(...), ISODate(...) and `new Date(...)`
+        _ => theme.bson_type_color,
+    }
+}
+
+// index just past the closing quote of the string starting at `start`
+fn skip_string(chars: &[char], start: usize) -> usize {
+    let quote = chars[start];
+    let mut pos = start + 1;
+    while pos < chars.len() {
+        match chars[pos] {
+            '\\' => pos += 2,
+            c if c == quote => return pos + 1,
+            _ => pos += 1,
+        }
+    }
+    chars.len()
+}
+
+// index just past the flags of the regex literal starting at `start`
+fn skip_regex(chars: &[char], start: usize) -> usize {
+    let mut pos = start + 1;
+    while pos < chars.len() {
+        match chars[pos] {
+            '\\' => pos += 2,
+            '/' => {
+                pos += 1;
+                while pos < chars.len() && chars[pos].is_ascii_alphabetic() {
+                    pos += 1;
+                }
+                return pos;
+            }
+            _ => pos += 1,
+        }
+    }
+    chars.len()
+}
+
+fn is_followed_by_colon(chars: &[char], pos: usize) -> bool {
+    chars[pos.min(chars.len())..]
+        .iter()
+        .find(|c| !c.is_whitespace())
+        == Some(&':')
+}
@@ -1,4 +1,118 @@
-pub mod database_list;
+pub mod aggregation;
 pub mod collection_list;
+pub mod connection;
+pub mod database_list;
 pub mod document_view;
-pub mod connection;
\ No newline at end of file
+pub mod editor;
+pub mod highlight;
+pub mod modal;
+pub mod update;
+
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+
+use crate::{
+    app::{diff::FieldChange, state::AppState},
+    syntax::{self, DisplayMode},
+};
+
+const SPINNER_FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// Error, in-progress or notice message to show in a footer instead of its
+/// key hints.
+pub fn status_text(state: &AppState) -> Option<String> {
+    if let Some(error) = &state.error {
+        Some(format!("Error: {}", error))
+    } else if state.loading {
+        let frame = SPINNER_FRAMES[state.spinner_frame % SPINNER_FRAMES.len()];
+        match &state.write_message {
+            // a notice here answers an Esc pressed during the write
+            Some(message) => Some(match &state.notice {
+                Some(notice) => format!("{} {} - {}", frame, message, notice),
+                None => format!("{} {} (cannot be cancelled)", frame, message),
+            }),
+            None => Some(format!(
+                "{} {} (Esc to cancel)",
+                frame, state.loading_message
+            )),
+        }
+    } else {
+        state.notice.clone()
+    }
+}
+
+/// `title` followed by the name of the connection profile in its color and
+/// a READ-ONLY badge while the connection refuses writes.
+pub fn header_line(title: String, state: &AppState) -> Line<'static> {
+    let mut spans = vec![Span::raw(title)];
+    if let Some(profile) = state
+        .connection
+        .as_ref()
+        .and_then(|conn| conn.profile.as_ref())
+    {
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled(
+            format!(" {} ", profile.name),
+            Style::default()
+                .fg(Color::Black)
+                .bg(profile.color().unwrap_or(Color::Gray))
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+    if let Some(tunnel) = state
+        .connection
+        .as_ref()
+        .and_then(|conn| conn.tunnel.as_ref())
+    {
+        let (text, color) = if tunnel.open {
+            (format!(" SSH {} ", tunnel.label), Color::Green)
+        } else {
+            (format!(" SSH {} CLOSED ", tunnel.label), Color::Red)
+        };
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled(
+            text,
+            Style::default()
+                .fg(Color::Black)
+                .bg(color)
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+    if state.is_read_only() {
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled(
+            " READ-ONLY ",
+            Style::default()
+                .fg(Color::White)
+                .bg(Color::Red)
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+    Line::from(spans)
+}
+
+/// One changed field as `+ path: value`, `- path: value` or
+/// `~ path: before → after`.
+pub fn change_line(change: &FieldChange) -> Line<'static> {
+    let value = |bson| syntax::to_compact_string(bson, DisplayMode::Shell);
+    let (marker, color, path, text) = match change {
+        FieldChange::Added { path, value: new } => ("+", Color::Green, path, value(new)),
+        FieldChange::Removed { path, value: old } => ("-", Color::Red, path, value(old)),
+        FieldChange::Changed {
+            path,
+            before,
+            after,
+        } => (
+            "~",
+            Color::Yellow,
+            path,
+            format!("{} → {}", value(before), value(after)),
+        ),
+    };
+    Line::from(vec![
+        Span::styled(format!("{} {}: ", marker, path), Style::default().fg(color)),
+        Span::raw(text),
+    ])
+}
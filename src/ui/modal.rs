@@ -0,0 +1,294 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+
+use mongodb::bson::Bson;
+
+use super::change_line;
+use crate::{
+    app::{external::EditPurpose, journal, modal::Modal, state::AppState, update},
+    syntax::{self, DisplayMode},
+};
+
+// changes listed in the confirmation dialog before the rest are summarised
+const MAX_CHANGES: usize = 15;
+
+pub fn render(f: &mut Frame, area: Rect, state: &AppState) {
+    let Some(modal) = &state.modal else {
+        return;
+    };
+
+    match modal {
+        Modal::EditError { edit, message } => {
+            let lines = vec![
+                Line::from(message.as_str()),
+                Line::from(""),
+                hints("Enter: edit again | Esc: discard"),
+            ];
+            let title = match edit.purpose {
+                EditPurpose::Profile { .. } => "Invalid profile",
+                _ => "Invalid document",
+            };
+            render_dialog(f, area, title, Color::Red, lines);
+        }
+        Modal::Passphrase {
+            input,
+            first,
+            creating,
+            error,
+            ..
+        } => {
+            let prompt = match (creating, first) {
+                (false, _) => "Master passphrase for ~/.mongonaut/credentials.enc",
+                (true, None) => "Choose a master passphrase to encrypt saved passwords with",
+                (true, Some(_)) => "Repeat the master passphrase",
+            };
+            let mut lines = vec![
+                Line::from(prompt),
+                Line::from(""),
+                Line::from(format!(
+                    "Passphrase: {}_",
+                    "*".repeat(input.chars().count())
+                )),
+                Line::from(""),
+            ];
+            if let Some(error) = error {
+                lines.push(Line::from(Span::styled(
+                    error.clone(),
+                    Style::default().fg(Color::Red),
+                )));
+                lines.push(Line::from(""));
+            }
+            lines.push(hints(
+                "Enter: unlock | Esc: continue without saved passwords",
+            ));
+            let title = if *creating {
+                "Create credential file"
+            } else {
+                "Unlock credentials"
+            };
+            render_dialog(f, area, title, Color::Cyan, lines);
+        }
+        Modal::Password { key, input, .. } => {
+            let lines = vec![
+                Line::from(format!("Password for {}", key)),
+                Line::from(""),
+                Line::from(format!("Password: {}_", "*".repeat(input.chars().count()))),
+                Line::from(""),
+                hints("Enter: connect | Esc: cancel"),
+            ];
+            render_dialog(f, area, "Password required", Color::Cyan, lines);
+        }
+        Modal::ConfirmDeleteProfile { name } => {
+            let lines = vec![
+                Line::from(format!("Profile: {}", name)),
+                Line::from(""),
+                hints("y/Enter: delete | n/Esc: cancel"),
+            ];
+            render_dialog(f, area, "Delete connection profile?", Color::Red, lines);
+        }
+        Modal::ConfirmDelete {
+            database,
+            collection,
+            filter,
+            count,
+            bulk,
+            typed,
+        } => {
+            let filter =
+                syntax::to_compact_string(&Bson::Document(filter.clone()), DisplayMode::Shell);
+            let mut lines = vec![
+                Line::from(format!("Namespace: {}.{}", database, collection)),
+                Line::from(format!("Filter:    {}", filter)),
+                Line::from(format!("Documents: {}", count)),
+                Line::from(""),
+            ];
+            if *count as usize > journal::MAX_DOCUMENTS {
+                lines.push(not_undoable());
+                lines.push(Line::from(""));
+            }
+            if *bulk {
+                lines.push(Line::from(format!(
+                    "Type the collection name to confirm: {}_",
+                    typed
+                )));
+                lines.push(Line::from(""));
+                lines.push(hints("Enter: delete | Esc: cancel"));
+            } else {
+                lines.push(hints("y/Enter: delete | n/Esc: cancel"));
+            }
+            let title = if *bulk {
+                "Delete matching documents?"
+            } else {
+                "Delete document?"
+            };
+            render_dialog(f, area, title, Color::Red, lines);
+        }
+        Modal::ConfirmUpdate {
+            database,
+            collection,
+            filter,
+            update,
+            count,
+        } => {
+            let filter =
+                syntax::to_compact_string(&Bson::Document(filter.clone()), DisplayMode::Shell);
+            let update =
+                syntax::to_compact_string(&update::update_to_bson(update), DisplayMode::Shell);
+            let mut lines = vec![
+                Line::from(format!("Namespace: {}.{}", database, collection)),
+                Line::from(format!("Filter:    {}", filter)),
+                Line::from(format!("Update:    {}", update)),
+                Line::from(format!("Documents: {}", count)),
+                Line::from(""),
+            ];
+            if *count as usize > journal::MAX_DOCUMENTS {
+                lines.push(not_undoable());
+                lines.push(Line::from(""));
+            }
+            lines.push(hints("y/Enter: update | n/Esc: cancel"));
+            render_dialog(f, area, "Update matching documents?", Color::Yellow, lines);
+        }
+        Modal::CloneTarget {
+            databases,
+            database,
+            collections,
+            collection,
+            choosing_collection,
+            loading,
+            ..
+        } => {
+            let field = |label: &str, value: &str, focused: bool| {
+                let style = if focused {
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default()
+                };
+                let marker = if focused { "> " } else { "  " };
+                Line::from(Span::styled(
+                    format!("{}{:<12}◀ {} ▶", marker, label, value),
+                    style,
+                ))
+            };
+            let lines = vec![
+                field("Database:", &databases[*database], !choosing_collection),
+                field(
+                    "Collection:",
+                    if *loading {
+                        "loading..."
+                    } else {
+                        &collections[*collection]
+                    },
+                    *choosing_collection,
+                ),
+                Line::from(""),
+                hints("←/→: change | ↑/↓: database/collection | Enter: edit copy | Esc: cancel"),
+            ];
+            render_dialog(f, area, "Copy document to", Color::Cyan, lines);
+        }
+        Modal::ConfirmUndo => {
+            let Some(entry) = state.journal.last() else {
+                return;
+            };
+            let mut lines = vec![
+                Line::from(format!("Undo the {}", entry.label)),
+                Line::from(format!(
+                    "Namespace: {}.{}",
+                    entry.database, entry.collection
+                )),
+                Line::from(""),
+            ];
+            let earlier = state.journal.len() - 1;
+            if earlier > 0 {
+                lines.push(Line::from(format!(
+                    "{} earlier write(s) can be undone after this",
+                    earlier
+                )));
+                lines.push(Line::from(""));
+            }
+            lines.push(hints("y/Enter: undo | n/Esc: cancel"));
+            render_dialog(f, area, "Undo last write?", Color::Yellow, lines);
+        }
+        Modal::ConfirmReplace { changes, .. } => {
+            let mut lines: Vec<Line> = changes.iter().take(MAX_CHANGES).map(change_line).collect();
+            if changes.len() > MAX_CHANGES {
+                lines.push(Line::from(format!(
+                    "... and {} more",
+                    changes.len() - MAX_CHANGES
+                )));
+            }
+            lines.push(Line::from(""));
+            lines.push(hints("y/Enter: save | e: edit again | Esc: discard"));
+            render_dialog(f, area, "Save changes?", Color::Yellow, lines);
+        }
+    }
+}
+
+fn not_undoable() -> Line<'static> {
+    Line::from(Span::styled(
+        format!(
+            "More than {} documents: this cannot be undone",
+            journal::MAX_DOCUMENTS
+        ),
+        Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+    ))
+}
+
+fn hints(text: &str) -> Line<'static> {
+    Line::from(Span::styled(
+        text.to_string(),
+        Style::default().fg(Color::Gray),
+    ))
+}
+
+// draws `lines` in a box centred on `area`
+fn render_dialog(f: &mut Frame, area: Rect, title: &str, color: Color, lines: Vec<Line>) {
+    let width = area.width.saturating_sub(4).min(80);
+    let inner = width.saturating_sub(2).max(1) as usize;
+    let rows: usize = lines
+        .iter()
+        .map(|line| line.width().max(1).div_ceil(inner))
+        .sum();
+    let height = (rows as u16 + 2).min(area.height);
+    let dialog = centered(area, width, height);
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(color))
+        .title(Span::styled(
+            title.to_string(),
+            Style::default().fg(color).add_modifier(Modifier::BOLD),
+        ));
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(Clear, dialog);
+    f.render_widget(paragraph, dialog);
+}
+
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(area.height.saturating_sub(height) / 2),
+            Constraint::Length(height),
+            Constraint::Min(0),
+        ])
+        .split(area);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Length(area.width.saturating_sub(width) / 2),
+            Constraint::Length(width),
+            Constraint::Min(0),
+        ])
+        .split(vertical[1])[1]
+}
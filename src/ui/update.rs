@@ -0,0 +1,139 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph, Wrap},
+};
+
+use super::{change_line, editor, header_line, status_text};
+use crate::{
+    app::{state::AppState, update::UpdateFocus},
+    syntax::{self, DisplayMode},
+};
+
+pub fn render(f: &mut Frame, area: Rect, state: &AppState) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
+        .split(area);
+
+    let left_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Length(6), // filter
+            Constraint::Min(0),
+            Constraint::Length(3),
+        ])
+        .split(chunks[0]);
+
+    render_header(f, left_chunks[0], state);
+    render_editor(f, left_chunks[1], state, UpdateFocus::Filter);
+    render_editor(f, left_chunks[2], state, UpdateFocus::Update);
+    render_footer(f, left_chunks[3], state);
+    render_preview(f, chunks[1], state);
+}
+
+fn render_header(f: &mut Frame, area: Rect, state: &AppState) {
+    let title = if let (Some(db), Some(coll)) = (&state.current_database, &state.current_collection)
+    {
+        format!("Update: {}.{}", db, coll)
+    } else {
+        "Update".to_string()
+    };
+
+    let header = Paragraph::new(header_line(title, state))
+        .style(
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        )
+        .block(Block::default().borders(Borders::ALL));
+
+    f.render_widget(header, area);
+}
+
+fn render_editor(f: &mut Frame, area: Rect, state: &AppState, part: UpdateFocus) {
+    let update = &state.update;
+    let focused = update.focus == part;
+    let style = if focused {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default().fg(Color::White)
+    };
+
+    let (text, title) = match part {
+        UpdateFocus::Filter => (&update.filter, "Filter"),
+        UpdateFocus::Update => (
+            &update.update,
+            "Update ({$set: ...}, {$unset: ...}, {$inc: ...} or a pipeline array)",
+        ),
+    };
+    let block = Block::default().borders(Borders::ALL).title(title);
+
+    editor::render(f, area, text, block, style, focused);
+}
+
+fn render_preview(f: &mut Frame, area: Rect, state: &AppState) {
+    let update = &state.update;
+
+    let mut lines = Vec::new();
+    match update.matched {
+        None => lines.push(Line::from("Press Ctrl+R for a dry run")),
+        Some(matched) => {
+            lines.push(Line::from(Span::styled(
+                format!("{} document(s) match the filter", matched),
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+            if let Some(note) = &update.sample_note {
+                lines.push(Line::from(Span::styled(
+                    note.clone(),
+                    Style::default().fg(Color::Yellow),
+                )));
+            }
+            for sample in &update.samples {
+                lines.push(Line::from(""));
+                let id = syntax::to_compact_string(&sample.id, DisplayMode::Shell);
+                lines.push(Line::from(Span::styled(
+                    format!("_id: {}", id),
+                    Style::default().fg(Color::Cyan),
+                )));
+                if sample.changes.is_empty() {
+                    lines.push(Line::from(Span::styled(
+                        "  unchanged",
+                        Style::default().fg(Color::DarkGray),
+                    )));
+                }
+                for change in &sample.changes {
+                    let mut line = change_line(change);
+                    line.spans.insert(0, Span::raw("  "));
+                    lines.push(line);
+                }
+            }
+        }
+    }
+
+    let mut title = "Dry run (sample before → after, PgUp/PgDn to scroll)".to_string();
+    if update.matched.is_some() && !update.is_dry_run_current() {
+        title.push_str(" - outdated, Ctrl+R to run again");
+    }
+
+    let paragraph = Paragraph::new(lines)
+        .style(Style::default().fg(Color::White))
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .wrap(Wrap { trim: false })
+        .scroll((update.preview_scroll as u16, 0));
+
+    f.render_widget(paragraph, area);
+}
+
+fn render_footer(f: &mut Frame, area: Rect, state: &AppState) {
+    let footer_text = "Ctrl+R: dry run | Ctrl+X: update | Tab: filter/update | Esc: back";
+    let footer_text = status_text(state).unwrap_or_else(|| footer_text.to_string());
+    let footer = Paragraph::new(footer_text)
+        .style(Style::default().fg(Color::Gray))
+        .block(Block::default().borders(Borders::ALL));
+
+    f.render_widget(footer, area);
+}